@@ -0,0 +1,216 @@
+use crate::{date_range::DateRange, observable::ObservableRange, survey::Survey};
+use chrono::{Datelike, NaiveDate};
+use std::collections::{BTreeMap, HashMap};
+
+const DAYS_PER_YEAR: f64 = 365.25;
+
+// the mean recorded value of each calendar month across every year, January
+// first, for plotting a climatological seasonal cycle. months without a
+// recording are None rather than zero so they aren't drawn as empty
+pub fn seasonal_average(surveys: &[Survey]) -> [Option<f64>; 12] {
+    let mut totals = [(0.0, 0usize); 12];
+    for survey in surveys.iter().filter(|survey| survey.has_recording()) {
+        let month_index = survey.date_observation().month0() as usize;
+        totals[month_index].0 += survey.get_value();
+        totals[month_index].1 += 1;
+    }
+    totals.map(|(total, count)| (count > 0).then(|| total / count as f64))
+}
+
+// least squares fit of the recorded values against their dates, as
+// (slope per day, intercept). the intercept is the fitted value on
+// start_date, the earliest survey's date. None with fewer than two distinct
+// dates, where there is no line to fit
+pub fn linear_trend(surveys: &[Survey]) -> Option<(f64, f64)> {
+    let recorded = surveys
+        .iter()
+        .filter(|survey| survey.has_recording())
+        .collect::<Vec<_>>();
+    let start_date = recorded
+        .iter()
+        .map(|survey| survey.date_observation())
+        .min()?;
+    let points = recorded
+        .iter()
+        .map(|survey| {
+            let days = (survey.date_observation() - start_date).num_days() as f64;
+            (days, survey.get_value())
+        })
+        .collect::<Vec<_>>();
+    let count = points.len() as f64;
+    let mean_days = points.iter().map(|(days, _)| days).sum::<f64>() / count;
+    let mean_value = points.iter().map(|(_, value)| value).sum::<f64>() / count;
+    let covariance = points
+        .iter()
+        .map(|(days, value)| (days - mean_days) * (value - mean_value))
+        .sum::<f64>();
+    let variance = points
+        .iter()
+        .map(|(days, _)| (days - mean_days).powi(2))
+        .sum::<f64>();
+    if variance == 0.0 {
+        return None;
+    }
+    let slope = covariance / variance;
+    Some((slope, mean_value - slope * mean_days))
+}
+
+// a per-day slope from linear_trend as acre-feet per year
+pub fn slope_per_year(slope_per_day: f64) -> f64 {
+    slope_per_day * DAYS_PER_YEAR
+}
+
+// the stations' recorded values on one daily date axis spanning all of them,
+// one column per station in the order given, None where a station has no
+// recording that day. stations that weren't fetched get an all-None column
+pub fn observations_matrix(
+    observable_ranges: &[ObservableRange],
+    station_ids: &[&str],
+) -> (Vec<NaiveDate>, Vec<Vec<Option<f64>>>) {
+    let mut values_by_station: HashMap<&str, BTreeMap<NaiveDate, f64>> = station_ids
+        .iter()
+        .map(|station_id| (*station_id, BTreeMap::new()))
+        .collect();
+    for survey in observable_ranges
+        .iter()
+        .flat_map(|observable_range| observable_range.observations.iter())
+        .filter(|survey| survey.has_recording())
+    {
+        if let Some(values) = values_by_station.get_mut(survey.get_tap().station_id.as_str()) {
+            values.insert(survey.date_observation(), survey.get_value());
+        }
+    }
+    let first_date = values_by_station
+        .values()
+        .filter_map(|values| values.keys().next())
+        .min();
+    let last_date = values_by_station
+        .values()
+        .filter_map(|values| values.keys().next_back())
+        .max();
+    let dates = match (first_date, last_date) {
+        (Some(first_date), Some(last_date)) => DateRange(*first_date, *last_date).collect(),
+        _ => Vec::new(),
+    };
+    let columns = station_ids
+        .iter()
+        .map(|station_id| {
+            let values = &values_by_station[station_id];
+            dates.iter().map(|date| values.get(date).copied()).collect()
+        })
+        .collect();
+    (dates, columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{linear_trend, observations_matrix, seasonal_average, slope_per_year};
+    use crate::{
+        observable::ObservableRange,
+        observation::DataRecording,
+        survey::{Survey, Tap},
+    };
+    use chrono::{NaiveDate, TimeDelta};
+
+    fn monthly_survey(year: i32, month: u32, value: u32) -> Survey {
+        let date = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+        Survey::Monthly(Tap {
+            station_id: String::from("SHA"),
+            date_observation: date,
+            date_recording: date,
+            value: DataRecording::Recording(value),
+        })
+    }
+
+    #[test]
+    fn test_seasonal_average_two_years() {
+        // each month is 100 times its number in 2021 and 300 times in 2022,
+        // except June which is missing and December which only has 2021
+        let mut surveys = Vec::new();
+        for month in (1..=11).filter(|month| *month != 6) {
+            surveys.push(monthly_survey(2021, month, month * 100));
+            surveys.push(monthly_survey(2022, month, month * 300));
+        }
+        surveys.push(monthly_survey(2021, 12, 1200));
+        surveys.push(Survey::Monthly(Tap {
+            station_id: String::from("SHA"),
+            date_observation: NaiveDate::from_ymd_opt(2022, 12, 1).unwrap(),
+            date_recording: NaiveDate::from_ymd_opt(2022, 12, 1).unwrap(),
+            value: DataRecording::Dash,
+        }));
+        let averages = seasonal_average(&surveys);
+        for (month_index, average) in averages.iter().enumerate() {
+            let month = month_index as f64 + 1.0;
+            match month_index {
+                5 => assert_eq!(*average, None),
+                11 => assert_eq!(*average, Some(1200.0)),
+                _ => assert_eq!(*average, Some(month * 200.0)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_linear_trend_recovers_slope() {
+        let start_date = NaiveDate::from_ymd_opt(2020, 10, 1).unwrap();
+        // storage declining 20 af a day from 50,000, surveyed every third day
+        let surveys = (0..100)
+            .map(|i| {
+                let days = i * 3;
+                let date = start_date + TimeDelta::try_days(days).unwrap();
+                Survey::Daily(Tap {
+                    station_id: String::from("SHA"),
+                    date_observation: date,
+                    date_recording: date,
+                    value: DataRecording::Recording((50_000 - 20 * days) as u32),
+                })
+            })
+            .collect::<Vec<_>>();
+        let (slope, intercept) = linear_trend(&surveys).unwrap();
+        assert!((slope - -20.0).abs() < 1e-9);
+        assert!((intercept - 50_000.0).abs() < 1e-6);
+        assert!((slope_per_year(slope) - -7_305.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_linear_trend_needs_two_dates() {
+        assert_eq!(linear_trend(&[]), None);
+        assert_eq!(linear_trend(&[monthly_survey(2021, 1, 100)]), None);
+    }
+
+    #[test]
+    fn test_observations_matrix_aligns_offset_stations() {
+        let daily_range = |station_id: &str, days: &[u32]| -> ObservableRange {
+            days.iter()
+                .map(|day| {
+                    let date = NaiveDate::from_ymd_opt(2023, 1, *day).unwrap();
+                    Survey::Daily(Tap {
+                        station_id: String::from(station_id),
+                        date_observation: date,
+                        date_recording: date,
+                        value: DataRecording::Recording(*day * 10),
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into()
+        };
+        let observable_ranges = vec![
+            daily_range("SHA", &[1, 2, 3]),
+            daily_range("ORO", &[3, 5]),
+            daily_range("FOL", &[9]),
+        ];
+        let (dates, columns) = observations_matrix(&observable_ranges, &["ORO", "SHA"]);
+        assert_eq!(
+            dates,
+            (1..=5)
+                .map(|day| NaiveDate::from_ymd_opt(2023, 1, day).unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            columns,
+            vec![
+                vec![None, None, Some(30.0), None, Some(50.0)],
+                vec![Some(10.0), Some(20.0), Some(30.0), None, None],
+            ]
+        );
+    }
+}
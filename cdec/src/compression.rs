@@ -1,14 +1,249 @@
-use lzma_rs::xz_decompress;
-use std::io::{BufReader, Read};
-use tar::Archive;
+use crate::{
+    observable::ObservableRange,
+    survey::{CompressedStringRecord, Survey},
+};
+use csv::{ReaderBuilder, StringRecord, Writer};
+use lzma_rs::{xz_compress, xz_decompress};
+use ruzstd::{
+    decoding::StreamingDecoder,
+    encoding::{compress, CompressionLevel},
+};
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Cursor, Read},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    io::{BufWriter, Write},
+    sync::mpsc::{sync_channel, Receiver, SyncSender},
+    thread,
+};
+use tar::{Archive, Builder, Header};
 pub static CUMULATIVE_OBJECT: &[u8] = include_bytes!("../../fixtures/cumulative.tar.lzma");
 pub static CUMULATIVE_OBJECT_V2: &[u8] = include_bytes!("../../fixtures/cumulative_v2.tar.lzma");
 pub static OBSERVATIONS_OBJECT: &[u8] = include_bytes!("../../fixtures/reservoirs.tar.lzma");
 
-pub fn decompress_tar_file_to_csv_string(input: &[u8]) -> Vec<u8> {
-    let mut tar_object_buffer = BufReader::new(input);
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+// decompressed tar is handed from the xz decoder thread to the tar reader in
+// chunks of this size, with at most PIPE_CAPACITY chunks waiting
+#[cfg(not(target_arch = "wasm32"))]
+const PIPE_CHUNK_SIZE: usize = 64 * 1024;
+#[cfg(not(target_arch = "wasm32"))]
+const PIPE_CAPACITY: usize = 16;
+const TAR_BLOCK_SIZE: u64 = 512;
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum CompressionFormat {
+    #[default]
+    Lzma,
+    Zstd,
+}
+
+impl CompressionFormat {
+    // archives are told apart by their magic bytes, anything that isn't
+    // zstd is treated as lzma like the bundled fixtures
+    pub fn detect(header: &[u8]) -> Self {
+        if header.starts_with(&ZSTD_MAGIC) {
+            CompressionFormat::Zstd
+        } else {
+            CompressionFormat::Lzma
+        }
+    }
+}
+
+fn decompress<R: Read>(reader: R) -> io::Result<Vec<u8>> {
+    let mut tar_object_buffer = BufReader::new(reader);
+    let format = CompressionFormat::detect(tar_object_buffer.fill_buf()?);
     let mut decompress_output: Vec<u8> = Vec::new();
-    xz_decompress(&mut tar_object_buffer, &mut decompress_output).unwrap();
+    match format {
+        CompressionFormat::Lzma => {
+            xz_decompress(&mut tar_object_buffer, &mut decompress_output)
+                .map_err(io::Error::other)?;
+        }
+        CompressionFormat::Zstd => {
+            let mut decoder =
+                StreamingDecoder::new(&mut tar_object_buffer).map_err(io::Error::other)?;
+            decoder.read_to_end(&mut decompress_output)?;
+        }
+    }
+    Ok(decompress_output)
+}
+
+// the write half of a bounded pipe, what the xz decoder thread decodes into
+#[cfg(not(target_arch = "wasm32"))]
+struct PipeWriter(SyncSender<io::Result<Vec<u8>>>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "tar reader hung up"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// the read half of the pipe, handed to tar::Archive
+#[cfg(not(target_arch = "wasm32"))]
+struct PipeReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Cursor<Vec<u8>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            match self.receiver.recv() {
+                Ok(chunk) => self.chunk = Cursor::new(chunk?),
+                // the decoder thread is done
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+// a reader over the decompressed tar. zstd decodes as it is read, xz is
+// decoded on its own thread into a bounded pipe since lzma-rs only decodes
+// xz into a writer
+#[cfg(not(target_arch = "wasm32"))]
+fn decompressed_reader<R: Read + Send + 'static>(reader: R) -> io::Result<Box<dyn Read>> {
+    let mut tar_object_buffer = BufReader::new(reader);
+    match CompressionFormat::detect(tar_object_buffer.fill_buf()?) {
+        CompressionFormat::Lzma => {
+            let (sender, receiver) = sync_channel(PIPE_CAPACITY);
+            thread::spawn(move || {
+                let mut writer =
+                    BufWriter::with_capacity(PIPE_CHUNK_SIZE, PipeWriter(sender.clone()));
+                let decoded = xz_decompress(&mut tar_object_buffer, &mut writer)
+                    .map_err(io::Error::other)
+                    .and_then(|_| writer.flush());
+                if let Err(e) = decoded {
+                    let _ = sender.send(Err(e));
+                }
+            });
+            Ok(Box::new(PipeReader {
+                receiver,
+                chunk: Cursor::new(Vec::new()),
+            }))
+        }
+        CompressionFormat::Zstd => Ok(Box::new(
+            StreamingDecoder::new(tar_object_buffer).map_err(io::Error::other)?,
+        )),
+    }
+}
+
+// wasm has no threads to decode xz on, and lzma-rs can't decode xz a piece
+// at a time, so there only zstd archives are streamed. xz archives are still
+// decompressed whole before the tar is read
+#[cfg(target_arch = "wasm32")]
+fn decompressed_reader<R: Read + Send + 'static>(reader: R) -> io::Result<Box<dyn Read>> {
+    let mut tar_object_buffer = BufReader::new(reader);
+    match CompressionFormat::detect(tar_object_buffer.fill_buf()?) {
+        CompressionFormat::Lzma => Ok(Box::new(Cursor::new(decompress(tar_object_buffer)?))),
+        CompressionFormat::Zstd => Ok(Box::new(
+            StreamingDecoder::new(tar_object_buffer).map_err(io::Error::other)?,
+        )),
+    }
+}
+
+// the first file of a tar, read straight off the stream. the entries of a
+// tar::Archive borrow it, which an iterator handed back to the caller can't
+// hold, so the headers are read here instead
+fn first_file_in_tar<R: Read>(mut tar: R) -> io::Result<io::Take<R>> {
+    loop {
+        let mut header = Header::new_old();
+        match tar.read_exact(header.as_mut_bytes()) {
+            // a tar without any files has no records
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(tar.take(0)),
+            result => result?,
+        }
+        if header.as_bytes().iter().all(|byte| *byte == 0) {
+            return Ok(tar.take(0));
+        }
+        // the checksum is summed with its own field read as spaces
+        let checksum = header
+            .as_bytes()
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| match i {
+                148..156 => u32::from(b' '),
+                _ => u32::from(*byte),
+            })
+            .sum::<u32>();
+        if header.cksum()? != checksum {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "tar header checksum mismatch",
+            ));
+        }
+        let size = header.entry_size()?;
+        if header.entry_type().is_file() {
+            return Ok(tar.take(size));
+        }
+        // long names, pax headers and directories, padded to whole blocks
+        io::copy(
+            &mut (&mut tar).take(size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE),
+            &mut io::sink(),
+        )?;
+    }
+}
+
+// the records of a csv file, ending at the first error
+fn csv_records<R: Read>(csv_file: R) -> impl Iterator<Item = io::Result<StringRecord>> {
+    ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(csv_file)
+        .into_records()
+        .scan(false, |failed, record| {
+            if *failed {
+                return None;
+            }
+            *failed = record.is_err();
+            Some(record.map_err(io::Error::from))
+        })
+}
+
+pub fn compress_csv_to_tar_file(
+    csv_file_name: &str,
+    csv_file: &[u8],
+    format: CompressionFormat,
+) -> Vec<u8> {
+    let mut header = Header::new_gnu();
+    header.set_size(csv_file.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    let mut tar_builder = Builder::new(Vec::new());
+    tar_builder
+        .append_data(&mut header, csv_file_name, csv_file)
+        .unwrap();
+    let tar_file = tar_builder.into_inner().unwrap();
+    let mut compress_output: Vec<u8> = Vec::new();
+    match format {
+        CompressionFormat::Lzma => {
+            xz_compress(&mut tar_file.as_slice(), &mut compress_output).unwrap();
+        }
+        CompressionFormat::Zstd => {
+            compress(
+                tar_file.as_slice(),
+                &mut compress_output,
+                CompressionLevel::Fastest,
+            );
+        }
+    }
+    compress_output
+}
+
+pub fn decompress_tar_file_to_csv_string(input: &[u8]) -> Vec<u8> {
+    let decompress_output = decompress(input).unwrap();
     // read decompress_output with archive
     let mut tar_file_from_decompress_output = Archive::new(decompress_output.as_slice());
     let mut tar_file_enumerator = tar_file_from_decompress_output
@@ -25,11 +260,86 @@ pub fn decompress_tar_file_to_csv_string(input: &[u8]) -> Vec<u8> {
     buf
 }
 
+// yields the csv records of the first file in the archive one at a time.
+// the archive is decompressed and untarred only as far as the records are
+// read, so the decompressed tar is never held in memory, except for xz on
+// wasm (see decompressed_reader). a decoding or parse error is yielded as the
+// last item instead of panicking
+pub fn decompress_stream<R: Read + Send + 'static>(
+    reader: R,
+) -> impl Iterator<Item = io::Result<StringRecord>> {
+    let records: Box<dyn Iterator<Item = io::Result<StringRecord>>> =
+        match decompressed_reader(reader).and_then(first_file_in_tar) {
+            Ok(csv_file) => Box::new(csv_records(csv_file)),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        };
+    records
+}
+
+// merges new surveys into an existing archive of surveys and recompresses it
+// in the same format. xz and zstd can't be appended to in place, so the
+// archive is rewritten, but each station's old and new surveys go through
+// ObservableRange::merge so a date that was pulled again keeps the new value
+pub fn append_surveys_to_tar_file(
+    existing_archive: &[u8],
+    csv_file_name: &str,
+    new_surveys: Vec<Survey>,
+) -> io::Result<Vec<u8>> {
+    let format = CompressionFormat::detect(existing_archive);
+    // every survey is kept anyway, so the archive is decompressed up front
+    let decompress_output = decompress(existing_archive)?;
+    let existing_surveys = csv_records(first_file_in_tar(decompress_output.as_slice())?)
+        .map(|record| record.map(|record| Survey::from(CompressedStringRecord(record))))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut merged_by_station: BTreeMap<String, ObservableRange> = BTreeMap::new();
+    for surveys in [existing_surveys, new_surveys] {
+        let mut surveys_by_station: BTreeMap<String, Vec<Survey>> = BTreeMap::new();
+        for survey in surveys {
+            surveys_by_station
+                .entry(survey.get_tap().station_id.clone())
+                .or_default()
+                .push(survey);
+        }
+        for (station_id, surveys) in surveys_by_station {
+            let observable_range = ObservableRange::from(surveys);
+            let merged = match merged_by_station.remove(&station_id) {
+                Some(existing) => existing.merge(observable_range),
+                None => observable_range,
+            };
+            merged_by_station.insert(station_id, merged);
+        }
+    }
+    let mut writer = Writer::from_writer(vec![]);
+    for survey in merged_by_station
+        .into_values()
+        .flat_map(|observable_range| observable_range.observations)
+    {
+        let record: CompressedStringRecord = survey.into();
+        if writer.write_byte_record(record.0.as_byte_record()).is_err() {
+            panic!("Error: writing record failed");
+        }
+    }
+    Ok(compress_csv_to_tar_file(
+        csv_file_name,
+        &writer.into_inner().unwrap(),
+        format,
+    ))
+}
+
 #[cfg(test)]
 mod test {
-    use super::decompress_tar_file_to_csv_string;
+    use super::{
+        append_surveys_to_tar_file, compress_csv_to_tar_file, decompress_stream,
+        decompress_tar_file_to_csv_string, CompressionFormat,
+    };
+    use crate::{
+        observation::DataRecording,
+        survey::{CompressedStringRecord, Survey, Tap},
+    };
+    use chrono::NaiveDate;
     use hex_literal::hex;
     use sha3::{Digest, Sha3_384};
+    use std::io::Cursor;
     pub static TAR_TEST_OBJECT: &[u8] = include_bytes!("../../test-fixtures/output.tar.lzma");
     #[test]
     fn test_decompress_tar_file_to_csv_string() {
@@ -40,4 +350,135 @@ mod test {
         let result = hasher.finalize();
         assert_eq!(result[..], hex!("35f323d919c0c9ef3bd00f2421c28195506eb67cc971e7a9e3529742337ffdff3636ce839035fa273d90301245fff39d"));
     }
+
+    #[test]
+    fn test_decompress_stream_matches_eager_path() {
+        let eager_output = decompress_tar_file_to_csv_string(TAR_TEST_OBJECT);
+        let eager_records = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(eager_output.as_slice())
+            .records()
+            .map(|x| x.unwrap())
+            .collect::<Vec<_>>();
+        let streamed_records = decompress_stream(TAR_TEST_OBJECT)
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert!(!streamed_records.is_empty());
+        assert_eq!(streamed_records, eager_records);
+    }
+
+    #[test]
+    fn test_compression_formats_round_trip() {
+        let csv_file = decompress_tar_file_to_csv_string(TAR_TEST_OBJECT);
+        let lzma_object =
+            compress_csv_to_tar_file("output.csv", &csv_file, CompressionFormat::Lzma);
+        let zstd_object =
+            compress_csv_to_tar_file("output.csv", &csv_file, CompressionFormat::Zstd);
+        assert_eq!(
+            CompressionFormat::detect(&lzma_object),
+            CompressionFormat::Lzma
+        );
+        assert_eq!(
+            CompressionFormat::detect(&zstd_object),
+            CompressionFormat::Zstd
+        );
+        assert_eq!(decompress_tar_file_to_csv_string(&lzma_object), csv_file);
+        assert_eq!(decompress_tar_file_to_csv_string(&zstd_object), csv_file);
+        assert_eq!(
+            decompress_stream(Cursor::new(lzma_object))
+                .collect::<std::io::Result<Vec<_>>>()
+                .unwrap(),
+            decompress_stream(Cursor::new(zstd_object))
+                .collect::<std::io::Result<Vec<_>>>()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decompress_stream_errors_instead_of_panicking() {
+        // the second row is missing fields
+        let csv_file = "SHA,D,20230101,100\nSHA,D\nSHA,D,20230103,100\n";
+        let archive =
+            compress_csv_to_tar_file("output.csv", csv_file.as_bytes(), CompressionFormat::Lzma);
+        let mut records = decompress_stream(Cursor::new(archive));
+        assert!(records.next().unwrap().is_ok());
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+        // neither xz nor zstd
+        let mut records = decompress_stream(Cursor::new(b"not an archive".to_vec()));
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_decompress_stream_can_stop_early() {
+        let first_records = decompress_stream(TAR_TEST_OBJECT)
+            .take(3)
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(first_records.len(), 3);
+    }
+
+    #[test]
+    fn test_decompress_stream_reads_zstd_incrementally() {
+        // big enough to span several zstd blocks
+        let csv_file = (0..20_000)
+            .map(|i| format!("SHA,D,2023{:04},{}\n", i % 10_000, i))
+            .collect::<String>();
+        let archive =
+            compress_csv_to_tar_file("output.csv", csv_file.as_bytes(), CompressionFormat::Zstd);
+        // zstd is decoded the same way on wasm, so cutting off the last block
+        // still yields the records of the blocks before it, then an error
+        let truncated = archive[..archive.len() - 16].to_vec();
+        let records = decompress_stream(Cursor::new(truncated)).collect::<Vec<_>>();
+        let (last, leading) = records.split_last().unwrap();
+        assert!(last.is_err());
+        assert!(!leading.is_empty() && leading.len() < 20_000);
+        assert!(leading.iter().all(|record| record.is_ok()));
+        assert_eq!(&leading[0].as_ref().unwrap()[3], "0");
+    }
+
+    fn daily_survey(station_id: &str, day: u32, value: u32) -> Survey {
+        let date = NaiveDate::from_ymd_opt(2023, 1, day).unwrap();
+        Survey::Daily(Tap {
+            station_id: String::from(station_id),
+            date_observation: date,
+            date_recording: date,
+            value: DataRecording::Recording(value),
+        })
+    }
+
+    #[test]
+    fn test_append_surveys_round_trip() {
+        let existing_csv = "SHA,D,20230101,100\nSHA,D,20230102,100\nORO,D,20230101,50\n";
+        let existing_archive = compress_csv_to_tar_file(
+            "output.csv",
+            existing_csv.as_bytes(),
+            CompressionFormat::Zstd,
+        );
+        let new_surveys = vec![
+            daily_survey("SHA", 2, 200),
+            daily_survey("SHA", 3, 300),
+            daily_survey("VIL", 3, 10),
+        ];
+        let appended =
+            append_surveys_to_tar_file(&existing_archive, "output.csv", new_surveys).unwrap();
+        assert_eq!(
+            CompressionFormat::detect(&appended),
+            CompressionFormat::Zstd
+        );
+        let actual = decompress_stream(Cursor::new(appended))
+            .map(|record| Survey::from(CompressedStringRecord(record.unwrap())))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actual,
+            vec![
+                daily_survey("ORO", 1, 50),
+                daily_survey("SHA", 1, 100),
+                daily_survey("SHA", 2, 200),
+                daily_survey("SHA", 3, 300),
+                daily_survey("VIL", 3, 10),
+            ]
+        );
+    }
 }
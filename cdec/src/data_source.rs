@@ -0,0 +1,88 @@
+use crate::{
+    observable::ObservableRange,
+    observation::STORAGE_SENSOR_CODE,
+    reservoir::{Reservoir, CDEC_CSV_DATA_URL, CSV_OBJECT, DEFAULT_MAX_RETRIES},
+};
+use chrono::NaiveDate;
+use reqwest::Client;
+use std::future::Future;
+
+// where reservoirs and their surveys come from, so backends other than
+// CDEC can be swapped in without touching the commands
+pub trait DataSource {
+    fn fetch_reservoirs(&self) -> Vec<Reservoir>;
+    // None when the fetch failed, or when the reservoir has nothing new
+    // since last_known_date and can be skipped
+    fn fetch_observations(
+        &self,
+        reservoir: &Reservoir,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        last_known_date: Option<&NaiveDate>,
+    ) -> impl Future<Output = Option<ObservableRange>>;
+}
+
+// reservoirs from a capacity csv and surveys from the CDEC http api
+pub struct CdecSource {
+    client: Client,
+    reservoirs: Vec<Reservoir>,
+    max_retries: u32,
+    base_url: String,
+    sensor_code: Option<u32>,
+}
+
+impl CdecSource {
+    pub fn new(reservoir_list: &str, max_retries: u32) -> Self {
+        CdecSource {
+            client: Client::new(),
+            reservoirs: Reservoir::get_reservoir_vector_v2(reservoir_list),
+            max_retries,
+            base_url: String::from(CDEC_CSV_DATA_URL),
+            sensor_code: Some(STORAGE_SENSOR_CODE),
+        }
+    }
+
+    // reads the surveys from another CDEC sensor than storage, None reads every sensor
+    pub fn with_sensor_code(mut self, sensor_code: Option<u32>) -> Self {
+        self.sensor_code = sensor_code;
+        self
+    }
+
+    // points the survey requests at another CDEC compatible CSVDataServlet
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = String::from(base_url);
+        self
+    }
+}
+
+impl Default for CdecSource {
+    fn default() -> Self {
+        CdecSource::new(CSV_OBJECT, DEFAULT_MAX_RETRIES)
+    }
+}
+
+impl DataSource for CdecSource {
+    fn fetch_reservoirs(&self) -> Vec<Reservoir> {
+        self.reservoirs.clone()
+    }
+
+    async fn fetch_observations(
+        &self,
+        reservoir: &Reservoir,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        last_known_date: Option<&NaiveDate>,
+    ) -> Option<ObservableRange> {
+        reservoir
+            .get_surveys_v2_from(
+                &self.client,
+                self.base_url.as_str(),
+                start_date,
+                end_date,
+                self.sensor_code,
+                last_known_date,
+                self.max_retries,
+            )
+            .await
+    }
+}
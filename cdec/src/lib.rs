@@ -1,9 +1,13 @@
 #![feature(array_chunks)]
+pub mod analysis;
 pub mod compression;
+pub mod data_source;
 pub mod date_range;
+pub mod loader;
 pub mod normalized_naive_date;
 pub mod observable;
 pub mod observation;
+pub mod quality;
 pub mod reservoir;
 pub mod reservoir_observations;
 pub mod survey;
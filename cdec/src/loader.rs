@@ -0,0 +1,21 @@
+// options for reading csv input, comma delimited by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoaderOptions {
+    pub delimiter: u8,
+}
+
+impl Default for LoaderOptions {
+    fn default() -> Self {
+        LoaderOptions { delimiter: b',' }
+    }
+}
+
+impl LoaderOptions {
+    pub fn tab_delimited() -> Self {
+        LoaderOptions { delimiter: b'\t' }
+    }
+
+    pub fn pipe_delimited() -> Self {
+        LoaderOptions { delimiter: b'|' }
+    }
+}
@@ -11,6 +11,11 @@ pub struct NormalizedNaiveDate {
 }
 
 impl NormalizedNaiveDate {
+    /// Places `date` on the normalized water year, returning None for Feb 29
+    /// which the normalized year never contains.
+    pub fn from_date(date: NaiveDate) -> Option<NormalizedNaiveDate> {
+        NormalizedNaiveDate::from_md_opt(date.month(), date.day())
+    }
     pub fn from_md_opt(month: u32, day: u32) -> Option<NormalizedNaiveDate> {
         let normalized_year = NormalizedNaiveDate::derive_normalized_year(month);
         NaiveDate::from_ymd_opt(normalized_year, month, day).map(|_| NormalizedNaiveDate {
@@ -36,6 +41,12 @@ impl NormalizedNaiveDate {
         date_range
     }
 
+    // days since October 1 on the normalized water year, 0 through 364
+    pub fn day_of_water_year(&self) -> usize {
+        let (start, _end) = NormalizedNaiveDate::get_normalized_tuple_date_range();
+        (self.as_naive_date() - start).num_days() as usize
+    }
+
     pub fn normalized_year(&self) -> i32 {
         Self::derive_normalized_year(self.month)
     }
@@ -47,11 +58,16 @@ impl NormalizedNaiveDate {
         NaiveDate::from_ymd_opt(year, month, day).unwrap()
     }
 
+    // the normalized water year is the most recent one that ends in a
+    // non-leap year, so it always spans exactly 365 days
     pub fn derive_normalized_year(month: u32) -> i32 {
         let dt: DateTime<Local> = Local::now();
         let (first_year, second_year) = {
             let this = &dt.naive_local().date();
-            let year = this.year();
+            let mut year = this.year();
+            if NaiveDate::from_ymd_opt(year, 2, 29).is_some() {
+                year -= 1;
+            }
             (year - 1, year)
         };
         match month {
@@ -186,3 +202,57 @@ impl Iterator for NormalizedDateRange {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::NormalizedNaiveDate;
+    use chrono::{Datelike, NaiveDate};
+
+    #[test]
+    fn test_normalized_tuple_date_range_spans_365_days() {
+        let (start, end) = NormalizedNaiveDate::get_normalized_tuple_date_range();
+        assert_eq!((start.month(), start.day()), (10, 1));
+        assert_eq!((end.month(), end.day()), (9, 30));
+        assert_eq!((end - start).num_days() + 1, 365);
+    }
+
+    #[test]
+    fn test_day_of_water_year() {
+        let day = |year, month, day| {
+            let date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+            NormalizedNaiveDate::from_date(date)
+                .unwrap()
+                .day_of_water_year()
+        };
+        assert_eq!(day(2019, 10, 1), 0);
+        assert_eq!(day(2020, 1, 1), 92);
+        assert_eq!(day(2020, 3, 1), 151);
+        assert_eq!(day(2021, 3, 1), 151);
+        assert_eq!(day(2020, 9, 30), 364);
+    }
+
+    #[test]
+    fn test_from_date_skips_leap_day() {
+        let leap_day = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(NormalizedNaiveDate::from_date(leap_day), None);
+    }
+
+    #[test]
+    fn test_from_date_round_trip() {
+        let (start, _end) = NormalizedNaiveDate::get_normalized_tuple_date_range();
+        for date in [
+            NaiveDate::from_ymd_opt(1977, 10, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 2, 28).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2021, 9, 30).unwrap(),
+        ] {
+            let normalized = NormalizedNaiveDate::from_date(date).unwrap();
+            let normalized_date: NaiveDate = normalized.into();
+            assert_eq!(normalized_date.month(), date.month());
+            assert_eq!(normalized_date.day(), date.day());
+            assert!(normalized_date >= start);
+            let round_trip = NormalizedNaiveDate::from_date(normalized_date).unwrap();
+            assert_eq!(round_trip, normalized);
+        }
+    }
+}
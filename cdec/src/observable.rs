@@ -8,6 +8,8 @@ use chrono::{Datelike, NaiveDate, TimeDelta};
 use csv::{StringRecord, Writer};
 use easy_cast::Cast;
 use log::info;
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
 
@@ -47,6 +49,9 @@ pub trait CompressedSurveyBuilder {
     fn pad_end(&mut self);
 }
 
+// data this many days behind is likely a stalled pipeline rather than a lag
+pub const STALE_DATA_DAYS: i64 = 14;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ObservableRange {
     pub observations: Vec<Survey>,
@@ -58,9 +63,25 @@ pub struct ObservableRange {
 pub trait ObservableRangeRunner {
     fn run_csv(&self) -> String;
     fn run_csv_v2(&self) -> String;
+    // the most recent date surveyed across all the reservoirs
+    fn latest_observation_date(&self) -> Option<NaiveDate>;
+    // days between the latest survey and today, a sign the data stopped updating
+    fn staleness_days(&self, today: NaiveDate) -> Option<i64>;
 }
 
 impl ObservableRangeRunner for Vec<ObservableRange> {
+    fn latest_observation_date(&self) -> Option<NaiveDate> {
+        self.iter()
+            .flat_map(|observable_range| observable_range.observations.iter())
+            .map(|survey| survey.date_observation())
+            .max()
+    }
+
+    fn staleness_days(&self, today: NaiveDate) -> Option<i64> {
+        self.latest_observation_date()
+            .map(|latest_date| (today - latest_date).num_days())
+    }
+
     fn run_csv(&self) -> String {
         info!("ran all surveys!");
         let mut observations_downloaded = self.clone();
@@ -172,6 +193,122 @@ impl ObservableRangeRunner for Vec<ObservableRange> {
     }
 }
 
+// a reservoir's latest reading in context, for a dashboard tile
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurrentConditions {
+    pub latest_date: NaiveDate,
+    pub latest_value: f64,
+    // None when the capacity is unknown
+    pub percent_of_capacity: Option<f64>,
+    // against the mean of the same calendar day in earlier years,
+    // None when there is no earlier reading for that day
+    pub percent_of_normal: Option<f64>,
+}
+
+impl ObservableRange {
+    // the recorded survey closest to date, for a crosshair hovering between
+    // readings; a date halfway between two surveys takes the earlier one
+    pub fn nearest_observation(&self, date: NaiveDate) -> Option<&Survey> {
+        let mut surveys = self
+            .observations
+            .iter()
+            .filter(|survey| survey.has_recording())
+            .collect::<Vec<_>>();
+        surveys.sort();
+        let after = surveys.partition_point(|survey| survey.date_observation() < date);
+        let before = after.checked_sub(1).and_then(|index| surveys.get(index));
+        match (before, surveys.get(after)) {
+            (Some(before), Some(after)) => {
+                let days_before = date - before.date_observation();
+                let days_after = after.date_observation() - date;
+                if days_after < days_before {
+                    Some(*after)
+                } else {
+                    Some(*before)
+                }
+            }
+            (before, after) => before.or(after).copied(),
+        }
+    }
+
+    // day-over-day change in storage, positive when inflow outpaces releases,
+    // for each day after start through end, so the changes sum to the change
+    // from start to end. gaps between surveys are interpolated linearly, also
+    // across the edges of the window, so each missing day gets an even share
+    // of the change across the gap
+    pub fn net_change(&self, start: NaiveDate, end: NaiveDate) -> Vec<(NaiveDate, f64)> {
+        let mut surveys = self
+            .observations
+            .iter()
+            .filter(|survey| survey.has_recording())
+            .collect::<Vec<_>>();
+        surveys.sort();
+        surveys
+            .windows(2)
+            .flat_map(|pair| {
+                let (start, end) = (pair[0], pair[1]);
+                let start_date = start.date_observation();
+                let days = (end.date_observation() - start_date).num_days();
+                let daily_change = (end.get_value() - start.get_value()) / days.max(1) as f64;
+                (1..=days)
+                    .map(move |day| (start_date + TimeDelta::try_days(day).unwrap(), daily_change))
+            })
+            .filter(|(date, _)| start < *date && *date <= end)
+            .collect()
+    }
+
+    pub fn current_conditions(&self, capacity: Option<i32>) -> Option<CurrentConditions> {
+        let latest = self
+            .observations
+            .iter()
+            .filter(|survey| survey.has_recording())
+            .max()?;
+        let latest_date = latest.date_observation();
+        let latest_value = latest.get_value();
+        let same_day_values = self
+            .observations
+            .iter()
+            .filter(|survey| survey.has_recording())
+            .map(|survey| (survey.date_observation(), survey.get_value()))
+            .filter(|(date, _)| {
+                date.year() < latest_date.year()
+                    && (date.month(), date.day()) == (latest_date.month(), latest_date.day())
+            })
+            .map(|(_, value)| value)
+            .collect::<Vec<_>>();
+        let normal = same_day_values.iter().sum::<f64>() / same_day_values.len() as f64;
+        let percent_of_normal =
+            (!same_day_values.is_empty() && normal > 0.0).then(|| latest_value / normal * 100.0);
+        let percent_of_capacity = capacity.filter(|capacity| *capacity > 0).map(|capacity| {
+            let capacity: f64 = capacity.cast();
+            latest_value / capacity * 100.0
+        });
+        Some(CurrentConditions {
+            latest_date,
+            latest_value,
+            percent_of_capacity,
+            percent_of_normal,
+        })
+    }
+
+    // unions two pulls of the same reservoir, keeping one survey per date.
+    // other is taken as the later pull, so its survey wins on shared dates
+    pub fn merge(self, other: ObservableRange) -> ObservableRange {
+        let mut surveys_by_date: BTreeMap<NaiveDate, Survey> = BTreeMap::new();
+        for survey in self.observations.into_iter().chain(other.observations) {
+            surveys_by_date.insert(survey.date_observation(), survey);
+        }
+        let mut month_datum = self.month_datum;
+        month_datum.extend(other.month_datum);
+        ObservableRange {
+            observations: surveys_by_date.into_values().collect(),
+            start_date: self.start_date.min(other.start_date),
+            end_date: self.end_date.max(other.end_date),
+            month_datum,
+        }
+    }
+}
+
 impl From<Vec<Survey>> for ObservableRange {
     fn from(value: Vec<Survey>) -> Self {
         let mut working_vector = value.clone();
@@ -354,76 +491,310 @@ impl Ord for MonthDatum {
 
 pub trait InterpolateObservableRanges {
     fn interpolate_reservoir_observations(&mut self);
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    fn par_interpolate_reservoir_observations(&mut self);
 }
 
 impl InterpolateObservableRanges for Vec<ObservableRange> {
     fn interpolate_reservoir_observations(&mut self) {
         // at this point, the observable ranges are retained, sorted, and the dates are well bounded
         for reservoir_observable_range in self {
-            let capacity = ((reservoir_observable_range.end_date
-                - reservoir_observable_range.start_date)
-                .num_days()
-                + 1) as usize;
-            let observation_clone = reservoir_observable_range.observations.clone();
-            let mut reservoir_survey_hashset = HashSet::new();
-            // interpolate
-            let surveys_slice = observation_clone.as_slice();
-            let windows = surveys_slice.windows(2);
-            for survey_window in windows {
-                let survey_0 = survey_window[0].clone();
-                let survey_1 = survey_window[1].clone();
-                let survey_tuple = (survey_0, survey_1);
-                let interpolation: Option<Vec<Survey>> = survey_tuple.interpolate_pair();
-                if let Some(vec_survey) = interpolation {
-                    for survey_interpolated in vec_survey {
-                        let _insert_result = reservoir_survey_hashset.insert(survey_interpolated);
-                    }
-                }
-            }
-            let reservoir_hash_set_len = reservoir_survey_hashset.len();
-            let delta;
-            // pad the end if need be
-            if reservoir_hash_set_len < capacity {
-                let mut tmp_date;
-                let mut tmp_survey;
-                delta = capacity - reservoir_hash_set_len;
-                let mut hash_set_as_vec = reservoir_survey_hashset.into_iter().collect::<Vec<_>>();
-                let most_recent = reservoir_observable_range.observations.last().unwrap();
-                let most_recent_tap = most_recent.get_tap();
-                let most_recent_date = most_recent_tap.date_observation;
-                for i in 0..delta {
-                    let num_of_days = i + 1;
-                    tmp_date = most_recent_date + TimeDelta::try_days(num_of_days as i64).unwrap();
-                    tmp_survey = Survey::Daily(Tap {
-                        station_id: most_recent_tap.station_id.clone(),
-                        date_observation: tmp_date,
-                        date_recording: tmp_date,
-                        value: most_recent_tap.value,
-                    });
-                    hash_set_as_vec.push(tmp_survey);
-                }
-                hash_set_as_vec.sort();
-                reservoir_observable_range.observations = hash_set_as_vec;
-            } else {
-                reservoir_observable_range.observations =
-                    reservoir_survey_hashset.into_iter().collect::<Vec<_>>();
+            interpolate_observable_range(reservoir_observable_range);
+        }
+    }
+
+    // each reservoir is interpolated independently of the others
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    fn par_interpolate_reservoir_observations(&mut self) {
+        self.par_iter_mut().for_each(interpolate_observable_range);
+    }
+}
+
+fn interpolate_observable_range(reservoir_observable_range: &mut ObservableRange) {
+    let capacity = ((reservoir_observable_range.end_date - reservoir_observable_range.start_date)
+        .num_days()
+        + 1) as usize;
+    let observation_clone = reservoir_observable_range.observations.clone();
+    let mut reservoir_survey_hashset = HashSet::new();
+    // interpolate
+    let surveys_slice = observation_clone.as_slice();
+    let windows = surveys_slice.windows(2);
+    for survey_window in windows {
+        let survey_0 = survey_window[0].clone();
+        let survey_1 = survey_window[1].clone();
+        let survey_tuple = (survey_0, survey_1);
+        let interpolation: Option<Vec<Survey>> = survey_tuple.interpolate_pair();
+        if let Some(vec_survey) = interpolation {
+            for survey_interpolated in vec_survey {
+                let _insert_result = reservoir_survey_hashset.insert(survey_interpolated);
             }
         }
     }
+    let reservoir_hash_set_len = reservoir_survey_hashset.len();
+    let delta;
+    // pad the end if need be
+    if reservoir_hash_set_len < capacity {
+        let mut tmp_date;
+        let mut tmp_survey;
+        delta = capacity - reservoir_hash_set_len;
+        let mut hash_set_as_vec = reservoir_survey_hashset.into_iter().collect::<Vec<_>>();
+        let most_recent = reservoir_observable_range.observations.last().unwrap();
+        let most_recent_tap = most_recent.get_tap();
+        let most_recent_date = most_recent_tap.date_observation;
+        for i in 0..delta {
+            let num_of_days = i + 1;
+            tmp_date = most_recent_date + TimeDelta::try_days(num_of_days as i64).unwrap();
+            tmp_survey = Survey::Daily(Tap {
+                station_id: most_recent_tap.station_id.clone(),
+                date_observation: tmp_date,
+                date_recording: tmp_date,
+                value: most_recent_tap.value,
+            });
+            hash_set_as_vec.push(tmp_survey);
+        }
+        hash_set_as_vec.sort();
+        reservoir_observable_range.observations = hash_set_as_vec;
+    } else {
+        reservoir_observable_range.observations =
+            reservoir_survey_hashset.into_iter().collect::<Vec<_>>();
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::collections::HashSet;
 
-    use chrono::NaiveDate;
+    use chrono::{Datelike, NaiveDate};
 
     use crate::{
         observation::DataRecording,
         survey::{Survey, Tap},
     };
 
-    use super::{InterpolateObservableRanges, MonthDatum, ObservableRange};
+    use super::{
+        CurrentConditions, InterpolateObservableRanges, MonthDatum, ObservableRange,
+        ObservableRangeRunner, STALE_DATA_DAYS,
+    };
+    #[test]
+    fn test_staleness_days() {
+        let surveys: Vec<ObservableRange> = [("SHA", 1), ("ORO", 20)]
+            .iter()
+            .map(|(station_id, day)| {
+                let date = NaiveDate::from_ymd_opt(2023, 1, *day).unwrap();
+                let observable_range: ObservableRange = vec![Survey::Daily(Tap {
+                    station_id: String::from(*station_id),
+                    date_observation: date,
+                    date_recording: date,
+                    value: DataRecording::Recording(100),
+                })]
+                .into();
+                observable_range
+            })
+            .collect();
+        let today = NaiveDate::from_ymd_opt(2023, 3, 1).unwrap();
+        // measured from the latest survey across all reservoirs
+        assert_eq!(surveys.staleness_days(today), Some(40));
+        assert!(surveys.staleness_days(today).unwrap() > STALE_DATA_DAYS);
+        let fresh = NaiveDate::from_ymd_opt(2023, 1, 21).unwrap();
+        assert_eq!(surveys.staleness_days(fresh), Some(1));
+        assert_eq!(Vec::<ObservableRange>::new().staleness_days(today), None);
+    }
+
+    #[test]
+    fn test_nearest_observation() {
+        let observable_range: ObservableRange = [(1, 100), (5, 500), (10, 1000)]
+            .iter()
+            .map(|(day, value)| {
+                let date = NaiveDate::from_ymd_opt(2023, 1, *day).unwrap();
+                Survey::Daily(Tap {
+                    station_id: String::from("SHA"),
+                    date_observation: date,
+                    date_recording: date,
+                    value: DataRecording::Recording(*value),
+                })
+            })
+            .collect::<Vec<_>>()
+            .into();
+        let nearest = |year: i32, month: u32, day: u32| {
+            observable_range
+                .nearest_observation(NaiveDate::from_ymd_opt(year, month, day).unwrap())
+                .map(|survey| survey.get_value())
+        };
+        assert_eq!(nearest(2023, 1, 4), Some(500.0));
+        assert_eq!(nearest(2023, 1, 2), Some(100.0));
+        assert_eq!(nearest(2023, 1, 5), Some(500.0));
+        assert_eq!(nearest(2023, 1, 8), Some(1000.0));
+        // halfway between the 1st and 5th takes the earlier reading
+        assert_eq!(nearest(2023, 1, 3), Some(100.0));
+        assert_eq!(nearest(2022, 12, 1), Some(100.0));
+        assert_eq!(nearest(2023, 2, 1), Some(1000.0));
+    }
+    #[test]
+    fn test_net_change() {
+        let observable_range: ObservableRange = [(1, 100), (2, 110), (5, 80), (6, 80)]
+            .iter()
+            .map(|(day, value)| {
+                let date = NaiveDate::from_ymd_opt(2023, 1, *day).unwrap();
+                Survey::Daily(Tap {
+                    station_id: String::from("SHA"),
+                    date_observation: date,
+                    date_recording: date,
+                    value: DataRecording::Recording(*value),
+                })
+            })
+            .collect::<Vec<_>>()
+            .into();
+        let net_change = |start_day: u32, end_day: u32| {
+            observable_range
+                .net_change(
+                    NaiveDate::from_ymd_opt(2023, 1, start_day).unwrap(),
+                    NaiveDate::from_ymd_opt(2023, 1, end_day).unwrap(),
+                )
+                .into_iter()
+                .map(|(date, change)| (date.day(), change))
+                .collect::<Vec<_>>()
+        };
+        // the drop from 110 to 80 over the gap is spread across three days
+        assert_eq!(
+            net_change(1, 6),
+            vec![(2, 10.0), (3, -10.0), (4, -10.0), (5, -10.0), (6, 0.0)]
+        );
+        // a window starting and ending inside the gap still takes its share of it
+        assert_eq!(net_change(3, 4), vec![(4, -10.0)]);
+        let from_gap = net_change(3, 6);
+        assert_eq!(from_gap, vec![(4, -10.0), (5, -10.0), (6, 0.0)]);
+        // 100 interpolated on the 3rd down to 80 on the 6th
+        assert_eq!(
+            from_gap.iter().map(|(_, change)| change).sum::<f64>(),
+            -20.0
+        );
+        assert!(net_change(7, 9).is_empty());
+    }
+    #[test]
+    fn test_current_conditions() {
+        let observable_range: ObservableRange = [
+            (2021, 5, 1, 3000),
+            (2022, 5, 1, 1000),
+            (2022, 5, 2, 9000),
+            (2023, 4, 30, 1500),
+            (2023, 5, 1, 1600),
+        ]
+        .iter()
+        .map(|(year, month, day, value)| {
+            let date = NaiveDate::from_ymd_opt(*year, *month, *day).unwrap();
+            Survey::Daily(Tap {
+                station_id: String::from("SHA"),
+                date_observation: date,
+                date_recording: date,
+                value: DataRecording::Recording(*value),
+            })
+        })
+        .collect::<Vec<_>>()
+        .into();
+        // normal for May 1 is the mean of 3000 and 1000
+        assert_eq!(
+            observable_range.current_conditions(Some(4000)),
+            Some(CurrentConditions {
+                latest_date: NaiveDate::from_ymd_opt(2023, 5, 1).unwrap(),
+                latest_value: 1600.0,
+                percent_of_capacity: Some(40.0),
+                percent_of_normal: Some(80.0),
+            })
+        );
+        let conditions = observable_range.current_conditions(None).unwrap();
+        assert_eq!(conditions.percent_of_capacity, None);
+    }
+    fn daily_range(start_day: u32, end_day: u32, value: u32) -> ObservableRange {
+        (start_day..=end_day)
+            .map(|day| {
+                let date = NaiveDate::from_ymd_opt(2023, 1, day).unwrap();
+                Survey::Daily(Tap {
+                    station_id: String::from("SHA"),
+                    date_observation: date,
+                    date_recording: date,
+                    value: DataRecording::Recording(value),
+                })
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+    #[test]
+    fn test_merge_overlapping_ranges() {
+        let merged = daily_range(1, 5, 100).merge(daily_range(4, 8, 200));
+        assert_eq!(
+            merged.start_date,
+            NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()
+        );
+        assert_eq!(
+            merged.end_date,
+            NaiveDate::from_ymd_opt(2023, 1, 8).unwrap()
+        );
+        let actual = merged
+            .observations
+            .iter()
+            .map(|survey| (survey.date_observation().day(), survey.get_value()))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            (1, 100.0),
+            (2, 100.0),
+            (3, 100.0),
+            (4, 200.0),
+            (5, 200.0),
+            (6, 200.0),
+            (7, 200.0),
+            (8, 200.0),
+        ];
+        assert_eq!(actual, expected);
+        assert_eq!(
+            merged.month_datum,
+            HashSet::from([MonthDatum::new(2023, 1)])
+        );
+    }
+    #[test]
+    fn test_merge_disjoint_ranges_widens() {
+        let merged = daily_range(20, 21, 100).merge(daily_range(1, 2, 200));
+        assert_eq!(
+            merged.start_date,
+            NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()
+        );
+        assert_eq!(
+            merged.end_date,
+            NaiveDate::from_ymd_opt(2023, 1, 21).unwrap()
+        );
+        assert_eq!(merged.observations.len(), 4);
+        assert!(merged.observations.is_sorted());
+    }
+    #[test]
+    fn test_latest_observation_date() {
+        let reservoir = |station_id: &str, dates: &[(i32, u32, u32)]| -> ObservableRange {
+            dates
+                .iter()
+                .map(|(year, month, day)| {
+                    let date = NaiveDate::from_ymd_opt(*year, *month, *day).unwrap();
+                    Survey::Daily(Tap {
+                        station_id: String::from(station_id),
+                        date_observation: date,
+                        date_recording: date,
+                        value: DataRecording::Recording(1),
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into()
+        };
+        let observable_ranges = vec![
+            reservoir("SHA", &[(2023, 3, 1), (2023, 3, 2)]),
+            reservoir("ORO", &[(2023, 3, 5), (2023, 2, 1)]),
+            reservoir("VIL", &[(2023, 1, 1)]),
+        ];
+        assert_eq!(
+            observable_ranges.latest_observation_date(),
+            NaiveDate::from_ymd_opt(2023, 3, 5)
+        );
+        assert_eq!(
+            Vec::<ObservableRange>::new().latest_observation_date(),
+            None
+        );
+    }
     #[test]
     fn interpolate_reservoir_observations_test() {
         let mut observations = Vec::with_capacity(10);
@@ -524,4 +895,40 @@ mod test {
         let expected = [observable_range_expected];
         assert_eq!(actual[0], expected[0]);
     }
+
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    #[test]
+    fn par_interpolate_reservoir_observations_matches_serial_test() {
+        let start_date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let end_date = NaiveDate::from_ymd_opt(2022, 12, 31).unwrap();
+        let mut serial = (0..20u32)
+            .map(|reservoir| {
+                let observations = (0..12u32)
+                    .map(|week| {
+                        let date =
+                            start_date + chrono::TimeDelta::try_days((week * 7).into()).unwrap();
+                        Survey::Daily(Tap {
+                            station_id: format!("R{reservoir:02}"),
+                            date_observation: date,
+                            date_recording: date,
+                            value: DataRecording::Recording(reservoir * 100 + week * 3),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                ObservableRange {
+                    observations,
+                    start_date,
+                    end_date,
+                    month_datum: HashSet::new(),
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut parallel = serial.clone();
+        serial.interpolate_reservoir_observations();
+        parallel.par_interpolate_reservoir_observations();
+        for observable_range in serial.iter_mut().chain(parallel.iter_mut()) {
+            observable_range.observations.sort();
+        }
+        assert_eq!(serial, parallel);
+    }
 }
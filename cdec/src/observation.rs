@@ -3,6 +3,7 @@ use crate::{
         decompress_tar_file_to_csv_string, CUMULATIVE_OBJECT, CUMULATIVE_OBJECT_V2,
         OBSERVATIONS_OBJECT,
     },
+    loader::LoaderOptions,
     reservoir::Reservoir,
     survey::{CompressedStringRecord, CumulativeSummedStringRecord},
 };
@@ -22,6 +23,16 @@ use std::{
 pub const DATE_FORMAT: &str = "%Y%m%d %H%M";
 pub const YEAR_FORMAT: &str = "%Y-%m-%d";
 pub const CSV_ROW_LENGTH: usize = 9;
+// CDEC sensor 15 is reservoir storage, reported in acre-feet
+pub const STORAGE_SENSOR_CODE: u32 = 15;
+pub const STORAGE_UNIT: &str = "AF";
+
+// the SensorNums parameter of a CDEC request, None asks for every sensor
+pub fn sensor_nums_query(sensor_code: Option<u32>) -> String {
+    sensor_code
+        .map(|sensor_code| format!("&SensorNums={}", sensor_code))
+        .unwrap_or_default()
+}
 
 #[derive(Debug, PartialEq, Clone, Copy, Hash)]
 pub enum ObservationError {
@@ -50,6 +61,8 @@ pub struct Observation {
     pub date_recording: NaiveDate,
     pub value: DataRecording,
     pub duration: Duration,
+    pub sensor_code: u32,
+    pub unit: String,
 }
 
 impl Observation {
@@ -108,11 +121,13 @@ impl Observation {
             let start_date_ref = start_date;
             let end_date_ref = end_date;
             async move {
+                // the daily totals are summed in acre-feet
                 Observation::get_observations(
                     client_ref,
                     reservoir.station_id.as_str(),
                     start_date_ref,
                     end_date_ref,
+                    Some(STORAGE_SENSOR_CODE),
                 )
                 .await
             }
@@ -137,19 +152,36 @@ impl Observation {
         Ok(date_water_btree)
     }
 
+    // None asks CDEC for every sensor of the station
     pub async fn get_observations(
         client: &Client,
         reservoir_id: &str,
         start_date: &NaiveDate,
         end_date: &NaiveDate,
+        sensor_code: Option<u32>,
     ) -> Result<Vec<Observation>, ObservationError> {
         let mut observations: Vec<Observation> = Vec::new();
-        let request_body_daily =
-            Observation::http_request_body(client, reservoir_id, start_date, end_date, "D").await;
-        let _request_body_monthly =
-            Observation::http_request_body(client, reservoir_id, start_date, end_date, "M").await;
+        let request_body_daily = Observation::http_request_body(
+            client,
+            reservoir_id,
+            start_date,
+            end_date,
+            "D",
+            sensor_code,
+        )
+        .await;
+        let _request_body_monthly = Observation::http_request_body(
+            client,
+            reservoir_id,
+            start_date,
+            end_date,
+            "M",
+            sensor_code,
+        )
+        .await;
         if let Ok(body) = request_body_daily {
-            if let Ok(mut obs) = Observation::request_to_observations(body) {
+            if let Ok(obs) = Observation::request_to_observations(body) {
+                let mut obs = Observation::filter_by_sensor(obs, sensor_code);
                 observations.append(obs.as_mut());
             } else {
                 return Err(ObservationError::HttpResponseParseError);
@@ -166,8 +198,15 @@ impl Observation {
         start_date: &NaiveDate,
         end_date: &NaiveDate,
     ) -> Result<Vec<StringRecord>, ObservationError> {
-        let request_body =
-            Observation::http_request_body(client, reservoir_id, start_date, end_date, "D").await;
+        let request_body = Observation::http_request_body(
+            client,
+            reservoir_id,
+            start_date,
+            end_date,
+            "D",
+            Some(STORAGE_SENSOR_CODE),
+        )
+        .await;
         if let Ok(body) = request_body {
             if let Ok(records) = Observation::request_to_string_records(body) {
                 Ok(records)
@@ -184,8 +223,9 @@ impl Observation {
         start_date: &NaiveDate,
         end_date: &NaiveDate,
         rate: &str,
+        sensor_code: Option<u32>,
     ) -> Result<String, reqwest::Error> {
-        let url = format!("http://cdec.water.ca.gov/dynamicapp/req/CSVDataServlet?Stations={}&SensorNums=15&dur_code={}&Start={}&End={}", reservoir_id, rate, start_date.format(YEAR_FORMAT), end_date.format(YEAR_FORMAT));
+        let url = format!("http://cdec.water.ca.gov/dynamicapp/req/CSVDataServlet?Stations={}{}&dur_code={}&Start={}&End={}", reservoir_id, sensor_nums_query(sensor_code), rate, start_date.format(YEAR_FORMAT), end_date.format(YEAR_FORMAT));
         let response = client.get(url).send().await?;
         response.text().await
     }
@@ -199,8 +239,32 @@ impl Observation {
             .collect::<Result<Vec<Observation>, _>>()
             .unwrap()
     }
+    // keeps only the observations of one sensor so storage and e.g. elevation
+    // readings of a station are never mixed, None keeps every sensor
+    pub fn filter_by_sensor(
+        observations: Vec<Observation>,
+        sensor_code: Option<u32>,
+    ) -> Vec<Observation> {
+        match sensor_code {
+            None => observations,
+            Some(sensor_code) => observations
+                .into_iter()
+                .filter(|observation| observation.sensor_code == sensor_code)
+                .collect(),
+        }
+    }
     fn request_to_observations(request_body: String) -> Result<Vec<Observation>, ObservationError> {
-        let string_records = Observation::request_to_string_records(request_body);
+        Observation::observations_from_csv_with_options(
+            request_body.as_str(),
+            &LoaderOptions::default(),
+        )
+    }
+    // parses a CDEC csv export with a header row, e.g. a tab delimited download
+    pub fn observations_from_csv_with_options(
+        csv_object: &str,
+        options: &LoaderOptions,
+    ) -> Result<Vec<Observation>, ObservationError> {
+        let string_records = Observation::csv_to_string_records(csv_object, options);
         let result = string_records
             .unwrap()
             .iter()
@@ -217,10 +281,17 @@ impl Observation {
     }
     fn request_to_string_records(
         request_body: String,
+    ) -> Result<Vec<StringRecord>, ObservationError> {
+        Observation::csv_to_string_records(request_body.as_str(), &LoaderOptions::default())
+    }
+    fn csv_to_string_records(
+        csv_object: &str,
+        options: &LoaderOptions,
     ) -> Result<Vec<StringRecord>, ObservationError> {
         let records = ReaderBuilder::new()
             .has_headers(true)
-            .from_reader(request_body.as_bytes())
+            .delimiter(options.delimiter)
+            .from_reader(csv_object.as_bytes())
             .records()
             .map(|x| x.expect("failed record parse"))
             .collect::<Vec<StringRecord>>();
@@ -252,6 +323,13 @@ impl TryFrom<StringRecord> for Observation {
             "M" => Ok(Duration::Monthly),
             _ => Err(()),
         };
+        let sensor_code = value
+            .get(2)
+            .unwrap()
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| ())?;
+        let unit = value.get(8).unwrap().trim().to_string();
         let date_recording_value = NaiveDate::parse_from_str(value.get(4).unwrap(), DATE_FORMAT);
         let date_observation_value = NaiveDate::parse_from_str(value.get(5).unwrap(), DATE_FORMAT);
         let data_value: Result<DataRecording, ()> = match value.get(6).unwrap() {
@@ -271,6 +349,8 @@ impl TryFrom<StringRecord> for Observation {
                 date_observation: date_observation_value.unwrap(),
                 value: data_value.unwrap(),
                 duration,
+                sensor_code,
+                unit,
             });
         }
         Err(())
@@ -284,6 +364,7 @@ impl Hash for Observation {
         self.date_recording.hash(state);
         self.value.hash(state);
         self.duration.hash(state);
+        self.sensor_code.hash(state);
     }
 }
 
@@ -296,7 +377,9 @@ impl Eq for Observation {}
 
 impl PartialEq for Observation {
     fn eq(&self, other: &Self) -> bool {
-        self.date_observation == other.date_observation && self.station_id == other.station_id
+        self.date_observation == other.date_observation
+            && self.station_id == other.station_id
+            && self.sensor_code == other.sensor_code
     }
 }
 
@@ -308,7 +391,8 @@ impl PartialOrd for Observation {
 
 #[cfg(test)]
 mod test {
-    use super::DataRecording;
+    use super::{sensor_nums_query, DataRecording, STORAGE_SENSOR_CODE, STORAGE_UNIT};
+    use crate::loader::LoaderOptions;
     use crate::observation::Observation;
     use chrono::NaiveDate;
     use reqwest::Client;
@@ -354,9 +438,15 @@ VIL,D,15,STORAGE,20220228 0000,20220228 0000,9597, ,AF
         let start_date = NaiveDate::from_ymd_opt(2022, 2, 15).unwrap();
         let end_date = NaiveDate::from_ymd_opt(2022, 2, 28).unwrap();
         let client = Client::new();
-        let observations =
-            Observation::http_request_body(&client, reservoir_id, &start_date, &end_date, "D")
-                .await;
+        let observations = Observation::http_request_body(
+            &client,
+            reservoir_id,
+            &start_date,
+            &end_date,
+            "D",
+            Some(STORAGE_SENSOR_CODE),
+        )
+        .await;
         assert_eq!(
             observations.unwrap().as_str().replace("\r\n", "\n"),
             STR_RESULT
@@ -373,8 +463,14 @@ VIL,D,15,STORAGE,20220228 0000,20220228 0000,9597, ,AF
         let start_date = NaiveDate::from_ymd_opt(2022, 2, 15).unwrap();
         let end_date = NaiveDate::from_ymd_opt(2022, 2, 28).unwrap();
         let client = Client::new();
-        let observations =
-            Observation::get_observations(&client, reservoir_id, &start_date, &end_date).await;
+        let observations = Observation::get_observations(
+            &client,
+            reservoir_id,
+            &start_date,
+            &end_date,
+            Some(STORAGE_SENSOR_CODE),
+        )
+        .await;
         assert_eq!(observations.unwrap().len(), 14);
     }
 
@@ -387,4 +483,57 @@ VIL,D,15,STORAGE,20220228 0000,20220228 0000,9597, ,AF
         let observations = Observation::request_to_observations(string_result).unwrap();
         assert_eq!(observations[0].value, DataRecording::Recording(9593));
     }
+
+    #[test]
+    fn test_filter_by_sensor() {
+        let string_result = String::from(
+            r#"STATION_ID,DURATION,SENSOR_NUMBER,SENSOR_TYPE,DATE TIME,OBS DATE,VALUE,DATA_FLAG,UNITS
+VIL,D,15,STORAGE,20220215 0000,20220215 0000,9593, ,AF
+VIL,D,6,RES ELE,20220215 0000,20220215 0000,1470, ,FEET
+VIL,D,15,STORAGE,20220216 0000,20220216 0000,9589, ,AF
+VIL,D,6,RES ELE,20220216 0000,20220216 0000,1469, ,FEET
+"#,
+        );
+        let observations = Observation::request_to_observations(string_result).unwrap();
+        assert_eq!(observations.len(), 4);
+        assert_eq!(observations[1].sensor_code, 6);
+        assert_eq!(observations[1].unit, "FEET");
+        let storage =
+            Observation::filter_by_sensor(observations.clone(), Some(STORAGE_SENSOR_CODE));
+        assert_eq!(storage.len(), 2);
+        for observation in &storage {
+            assert_eq!(observation.sensor_code, STORAGE_SENSOR_CODE);
+            assert_eq!(observation.unit, STORAGE_UNIT);
+        }
+        assert_eq!(storage[1].value, DataRecording::Recording(9589));
+        assert_eq!(Observation::filter_by_sensor(observations, None).len(), 4);
+    }
+
+    #[test]
+    fn test_sensor_nums_query() {
+        assert_eq!(
+            sensor_nums_query(Some(STORAGE_SENSOR_CODE)),
+            "&SensorNums=15"
+        );
+        assert_eq!(sensor_nums_query(None), "");
+    }
+
+    #[test]
+    fn test_observations_from_csv_with_options() {
+        let comma_delimited =
+            Observation::request_to_observations(String::from(STR_RESULT)).unwrap();
+        let tab_delimited = STR_RESULT.replace(',', "\t");
+        let actual = Observation::observations_from_csv_with_options(
+            tab_delimited.as_str(),
+            &LoaderOptions::tab_delimited(),
+        )
+        .unwrap();
+        assert_eq!(actual.len(), 14);
+        for (actual_observation, expected_observation) in actual.iter().zip(comma_delimited.iter())
+        {
+            assert_eq!(actual_observation, expected_observation);
+            assert_eq!(actual_observation.value, expected_observation.value);
+            assert_eq!(actual_observation.unit, expected_observation.unit);
+        }
+    }
 }
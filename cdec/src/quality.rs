@@ -0,0 +1,127 @@
+use crate::survey::Survey;
+
+// flags surveys whose change from the last unflagged survey exceeds
+// max_daily_pct_change percent per day; surveys must be sorted by date.
+// comparing against the last good value keeps the drop back from a spike
+// from being flagged along with the spike itself
+pub fn flag_outliers(surveys: &[Survey], max_daily_pct_change: f64) -> Vec<bool> {
+    let mut flags = Vec::with_capacity(surveys.len());
+    let mut last_good: Option<&Survey> = None;
+    for survey in surveys {
+        let is_outlier = match last_good {
+            None => false,
+            Some(previous) => {
+                let previous_value = previous.get_value();
+                let days = (survey.date_observation() - previous.date_observation())
+                    .num_days()
+                    .max(1) as f64;
+                if previous_value == 0.0 {
+                    false
+                } else {
+                    let pct_change =
+                        ((survey.get_value() - previous_value) / previous_value).abs() * 100.0;
+                    pct_change / days > max_daily_pct_change
+                }
+            }
+        };
+        if !is_outlier {
+            last_good = Some(survey);
+        }
+        flags.push(is_outlier);
+    }
+    flags
+}
+
+// replaces each survey's value with the median of the window of surveys
+// around it, removing isolated spikes while keeping genuine steps; surveys
+// must be sorted by date and windows are cut short at either end. an even
+// window reaches one survey further ahead than behind, and an even number
+// of values takes the lower of the two middle values so the result is
+// always a value that was actually observed
+pub fn median_filter(surveys: &[Survey], window: usize) -> Vec<Survey> {
+    let behind = window.saturating_sub(1) / 2;
+    let ahead = window / 2;
+    (0..surveys.len())
+        .map(|i| {
+            let start = i.saturating_sub(behind);
+            let end = (i + ahead + 1).min(surveys.len());
+            let mut window_values = surveys[start..end]
+                .iter()
+                .map(|survey| (survey.get_value(), survey.get_tap().value))
+                .collect::<Vec<_>>();
+            window_values.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mut survey = surveys[i].clone();
+            if let Some((_, median)) = window_values.get((window_values.len() - 1) / 2) {
+                survey.tap().value = *median;
+            }
+            survey
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flag_outliers, median_filter};
+    use crate::{
+        observation::DataRecording,
+        survey::{Survey, Tap},
+    };
+    use chrono::{NaiveDate, TimeDelta};
+
+    fn daily_surveys(values: &[u32]) -> Vec<Survey> {
+        let start_date = NaiveDate::from_ymd_opt(2022, 1, 1).unwrap();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let date = start_date + TimeDelta::try_days(i as i64).unwrap();
+                Survey::Daily(Tap {
+                    station_id: String::from("SHA"),
+                    date_observation: date,
+                    date_recording: date,
+                    value: DataRecording::Recording(*value),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_flag_outliers_single_spike() {
+        let surveys = daily_surveys(&[1000, 1005, 1010, 2100, 1015, 1020, 1025]);
+        let flags = flag_outliers(&surveys, 10.0);
+        assert_eq!(flags, vec![false, false, false, true, false, false, false]);
+    }
+
+    #[test]
+    fn test_flag_outliers_smooth_series() {
+        let surveys = daily_surveys(&[1000, 1020, 1040, 1060, 1080]);
+        let flags = flag_outliers(&surveys, 10.0);
+        assert!(flags.iter().all(|flag| !flag));
+    }
+
+    #[test]
+    fn test_median_filter_removes_spike() {
+        let surveys = daily_surveys(&[1000, 1000, 5000, 1000, 1000]);
+        let filtered = median_filter(&surveys, 3);
+        assert!(filtered.iter().all(|survey| survey.get_value() == 1000.0));
+        assert_eq!(
+            filtered[2].date_observation(),
+            surveys[2].date_observation()
+        );
+    }
+
+    #[test]
+    fn test_median_filter_keeps_step() {
+        let step = [1000, 1000, 1000, 1000, 2000, 2000, 2000, 2000];
+        let surveys = daily_surveys(&step);
+        let expected = step.iter().map(|value| *value as f64).collect::<Vec<_>>();
+        // an even window takes the lower middle value and still keeps the step
+        for window in [3, 4] {
+            let values = median_filter(&surveys, window)
+                .iter()
+                .map(|survey| survey.get_value())
+                .collect::<Vec<_>>();
+            assert_eq!(values, expected);
+        }
+    }
+}
@@ -1,18 +1,29 @@
 use crate::{
+    loader::LoaderOptions,
     observable::{CompressedSurveyBuilder, MonthDatum, ObservableRange},
-    observation::DataRecording,
+    observation::{sensor_nums_query, DataRecording, STORAGE_SENSOR_CODE},
     survey::Survey,
 };
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveTime};
 use csv::ReaderBuilder;
+use futures::{stream, StreamExt};
 use log::{info, warn};
-use reqwest::{Client, StatusCode};
-use std::{collections::HashSet, include_str, thread::sleep, time::Duration};
+use reqwest::{header::IF_MODIFIED_SINCE, Client, StatusCode};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    include_str,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 pub static CSV_OBJECT: &str = include_str!("../../fixtures/capacity.csv");
 pub static CSV_OBJECT_NO_POWELL_NO_MEAD: &str =
     include_str!("../../fixtures/capacity-no-powell-no-mead.csv");
 const YEAR_FORMAT: &str = "%Y-%m-%d";
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+pub const CDEC_CSV_DATA_URL: &str = "http://cdec.water.ca.gov/dynamicapp/req/CSVDataServlet";
+const STATION_METADATA_URL: &str = "https://cdec.water.ca.gov/dynamicapp/staMeta?station_id=";
+pub const UNKNOWN_STREAM: &str = "Unknown";
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Reservoir {
@@ -22,14 +33,17 @@ pub struct Reservoir {
     pub stream: String,
     pub capacity: i32,
     pub fill_year: i32,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
 }
 
 trait StringRecordsToSurveys {
-    fn response_to_surveys(&self) -> Option<ObservableRange>;
+    // sensor_code drops the rows of any other sensor, None keeps every row
+    fn response_to_surveys(&self, sensor_code: Option<u32>) -> Option<ObservableRange>;
 }
 
 impl StringRecordsToSurveys for String {
-    fn response_to_surveys(&self) -> Option<ObservableRange> {
+    fn response_to_surveys(&self, sensor_code: Option<u32>) -> Option<ObservableRange> {
         let mut m: HashSet<MonthDatum> = HashSet::new();
         let mut observations = ReaderBuilder::new()
             .has_headers(true)
@@ -37,6 +51,14 @@ impl StringRecordsToSurveys for String {
             .records()
             .filter_map(|x| {
                 let string_record = x.expect("failed record parse");
+                if let Some(sensor_code) = sensor_code {
+                    let row_sensor_code = string_record
+                        .get(2)
+                        .and_then(|field| field.trim().parse::<u32>().ok());
+                    if row_sensor_code != Some(sensor_code) {
+                        return None;
+                    }
+                }
                 let survey: Survey = string_record.try_into().unwrap();
                 let tap = survey.get_tap();
                 match tap.value {
@@ -77,103 +99,360 @@ fn get_default_capacity<'life>() -> &'life str {
     "0"
 }
 
-impl Reservoir {
-    async fn get_survey_general(
-        &self,
-        client: &Client,
-        start_date: &NaiveDate,
-        end_date: &NaiveDate,
-        duration_type: &str,
-    ) -> Option<ObservableRange> {
-        let max_tries = 3;
-        let mut sleep_millis: u64 = 1000; // Start with 1 second
-        let start_date_str = start_date.format(YEAR_FORMAT);
-        let end_date_str = end_date.format(YEAR_FORMAT);
+// number of retries after the first attempt of a CDEC request
+pub const DEFAULT_MAX_RETRIES: u32 = 2;
+const INITIAL_BACKOFF_MILLIS: u64 = 1000;
 
-        for attempt in 1..=max_tries {
-            let url = format!(
-                "http://cdec.water.ca.gov/dynamicapp/req/CSVDataServlet?Stations={}&SensorNums=15&dur_code={}&Start={}&End={}",
-                self.station_id.as_str(), duration_type, start_date_str, end_date_str
-            );
+// adds up to 50% jitter so concurrent reservoir fetches don't retry in lockstep
+fn jitter_millis(sleep_millis: u64) -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos() as u64)
+        .unwrap_or_default();
+    sleep_millis + nanos % (sleep_millis / 2 + 1)
+}
 
-            match client.get(&url).send().await {
-                Ok(response) => {
-                    if response.status() != StatusCode::OK {
-                        warn!(
-                            "Attempt {}/{}: Bad response status for {}: {}",
-                            attempt,
-                            max_tries,
-                            self.dam,
-                            response.status()
-                        );
-                    } else {
-                        match response.text().await {
-                            Ok(response_body) => {
-                                if response_body.len() <= 2 {
-                                    warn!(
-                                        "Attempt {}/{}: Empty response for {}",
-                                        attempt, max_tries, self.dam
-                                    );
-                                } else {
-                                    return response_body.response_to_surveys();
-                                }
-                            }
-                            Err(e) => {
+// waits out a retry backoff without blocking the executor,
+// so the other in-flight reservoir fetches keep going
+async fn backoff(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(duration).await;
+}
+
+// what a conditional CDEC GET came back with
+#[derive(Debug, PartialEq)]
+pub enum ConditionalBody {
+    Modified(String),
+    // nothing changed since the If-Modified-Since date, so the station can be skipped
+    NotModified,
+}
+
+// GETs a CDEC csv body, retrying failures and empty bodies with jittered exponential backoff
+pub async fn get_with_retries(
+    client: &Client,
+    url: &str,
+    name: &str,
+    max_retries: u32,
+    initial_backoff_millis: u64,
+) -> Option<String> {
+    match get_with_retries_conditional(client, url, name, None, max_retries, initial_backoff_millis)
+        .await
+    {
+        Some(ConditionalBody::Modified(response_body)) => Some(response_body),
+        _ => None,
+    }
+}
+
+// like get_with_retries but sends If-Modified-Since for the last known date,
+// so a 304 answers cheaply without downloading the body again
+pub async fn get_if_modified_since(
+    client: &Client,
+    url: &str,
+    name: &str,
+    last_known_date: &NaiveDate,
+    max_retries: u32,
+    initial_backoff_millis: u64,
+) -> Option<ConditionalBody> {
+    get_with_retries_conditional(
+        client,
+        url,
+        name,
+        Some(last_known_date),
+        max_retries,
+        initial_backoff_millis,
+    )
+    .await
+}
+
+async fn get_with_retries_conditional(
+    client: &Client,
+    url: &str,
+    name: &str,
+    if_modified_since: Option<&NaiveDate>,
+    max_retries: u32,
+    initial_backoff_millis: u64,
+) -> Option<ConditionalBody> {
+    let max_tries = max_retries + 1;
+    let mut sleep_millis: u64 = initial_backoff_millis;
+    for attempt in 1..=max_tries {
+        let request = match if_modified_since {
+            Some(date) => client.get(url).header(
+                IF_MODIFIED_SINCE,
+                date.and_time(NaiveTime::MIN)
+                    .format(HTTP_DATE_FORMAT)
+                    .to_string(),
+            ),
+            None => client.get(url),
+        };
+        match request.send().await {
+            Ok(response) => {
+                if response.status() == StatusCode::NOT_MODIFIED {
+                    info!("{} not modified since {:?}", name, if_modified_since);
+                    return Some(ConditionalBody::NotModified);
+                } else if response.status() != StatusCode::OK {
+                    warn!(
+                        "Attempt {}/{}: Bad response status for {}: {}",
+                        attempt,
+                        max_tries,
+                        name,
+                        response.status()
+                    );
+                } else {
+                    match response.text().await {
+                        Ok(response_body) => {
+                            if response_body.len() <= 2 {
                                 warn!(
-                                    "Attempt {}/{}: Failed to read response body for {}: {}",
-                                    attempt, max_tries, self.dam, e
+                                    "Attempt {}/{}: Empty response for {}",
+                                    attempt, max_tries, name
                                 );
+                            } else {
+                                return Some(ConditionalBody::Modified(response_body));
                             }
                         }
+                        Err(e) => {
+                            warn!(
+                                "Attempt {}/{}: Failed to read response body for {}: {}",
+                                attempt, max_tries, name, e
+                            );
+                        }
                     }
                 }
-                Err(e) => {
-                    warn!(
-                        "Attempt {}/{}: Request failed for {}: {}",
-                        attempt, max_tries, self.dam, e
-                    );
-                }
             }
-
-            if attempt < max_tries {
-                info!(
-                    "Sleeping for {} milliseconds before retry for {}",
-                    sleep_millis, self.dam
+            Err(e) => {
+                warn!(
+                    "Attempt {}/{}: Request failed for {}: {}",
+                    attempt, max_tries, name, e
                 );
-                sleep(Duration::from_millis(sleep_millis));
-                sleep_millis *= 2; // Exponential backoff
             }
         }
 
-        warn!("All attempts failed for {}", self.dam);
+        if attempt < max_tries {
+            let jittered_sleep_millis = jitter_millis(sleep_millis);
+            info!(
+                "Sleeping for {} milliseconds before retry for {}",
+                jittered_sleep_millis, name
+            );
+            backoff(Duration::from_millis(jittered_sleep_millis)).await;
+            sleep_millis *= 2; // Exponential backoff
+        }
+    }
+
+    warn!("All attempts failed for {}", name);
+    None
+}
+
+// pulls the Latitude and Longitude cells out of a CDEC station metadata page
+pub fn parse_station_metadata(page: &str) -> Option<(f64, f64)> {
+    let mut text = String::with_capacity(page.len());
+    let mut in_tag = false;
+    for c in page.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let first_number_after = |label: &str| {
+        let (_, rest) = text.split_once(label)?;
+        rest.split(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+            .find_map(|token| token.parse::<f64>().ok())
+    };
+    let latitude = first_number_after("Latitude")?;
+    let longitude = first_number_after("Longitude")?;
+    if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) {
+        Some((latitude, longitude))
+    } else {
         None
     }
+}
+
+// GETs a CDEC station metadata page and gives its (latitude, longitude)
+pub async fn get_station_coordinates(
+    client: &Client,
+    url: &str,
+    name: &str,
+    max_retries: u32,
+    initial_backoff_millis: u64,
+) -> Option<(f64, f64)> {
+    let page = get_with_retries(client, url, name, max_retries, initial_backoff_millis).await?;
+    let coordinates = parse_station_metadata(page.as_str());
+    if coordinates.is_none() {
+        warn!("no coordinates in station metadata for {}", name);
+    }
+    coordinates
+}
+
+impl Reservoir {
+    // None when the request failed, or when nothing changed since last_known_date
+    #[allow(clippy::too_many_arguments)]
+    async fn get_survey_general(
+        &self,
+        client: &Client,
+        base_url: &str,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        duration_type: &str,
+        sensor_code: Option<u32>,
+        last_known_date: Option<&NaiveDate>,
+        max_retries: u32,
+    ) -> Option<ConditionalBody> {
+        let start_date_str = start_date.format(YEAR_FORMAT);
+        let end_date_str = end_date.format(YEAR_FORMAT);
+        let url = format!(
+            "{}?Stations={}{}&dur_code={}&Start={}&End={}",
+            base_url,
+            self.station_id.as_str(),
+            sensor_nums_query(sensor_code),
+            duration_type,
+            start_date_str,
+            end_date_str
+        );
+        get_with_retries_conditional(
+            client,
+            url.as_str(),
+            self.dam.as_str(),
+            last_known_date,
+            max_retries,
+            INITIAL_BACKOFF_MILLIS,
+        )
+        .await
+    }
     pub async fn get_monthly_surveys(
         &self,
         client: &Client,
         start_date: &NaiveDate,
         end_date: &NaiveDate,
+        sensor_code: Option<u32>,
+        max_retries: u32,
     ) -> Option<ObservableRange> {
-        self.get_survey_general(client, start_date, end_date, "M")
+        match self
+            .get_survey_general(
+                client,
+                CDEC_CSV_DATA_URL,
+                start_date,
+                end_date,
+                "M",
+                sensor_code,
+                None,
+                max_retries,
+            )
             .await
+        {
+            Some(ConditionalBody::Modified(response_body)) => {
+                response_body.response_to_surveys(sensor_code)
+            }
+            _ => None,
+        }
     }
     pub async fn get_daily_surveys(
         &self,
         client: &Client,
         start_date: &NaiveDate,
         end_date: &NaiveDate,
+        sensor_code: Option<u32>,
+        max_retries: u32,
     ) -> Option<ObservableRange> {
-        self.get_survey_general(client, start_date, end_date, "D")
+        match self
+            .get_survey_general(
+                client,
+                CDEC_CSV_DATA_URL,
+                start_date,
+                end_date,
+                "D",
+                sensor_code,
+                None,
+                max_retries,
+            )
             .await
+        {
+            Some(ConditionalBody::Modified(response_body)) => {
+                response_body.response_to_surveys(sensor_code)
+            }
+            _ => None,
+        }
     }
+    // daily surveys filled in with monthly surveys for months without dailies,
+    // read from one sensor so storage and elevation are never mixed.
+    // with a last_known_date the requests are conditional, and a station whose
+    // dailies haven't changed since then is skipped and gives None
     pub async fn get_surveys_v2(
         &self,
         client: &Client,
         start_date: &NaiveDate,
         end_date: &NaiveDate,
+        sensor_code: Option<u32>,
+        last_known_date: Option<&NaiveDate>,
+        max_retries: u32,
+    ) -> Option<ObservableRange> {
+        self.get_surveys_v2_from(
+            client,
+            CDEC_CSV_DATA_URL,
+            start_date,
+            end_date,
+            sensor_code,
+            last_known_date,
+            max_retries,
+        )
+        .await
+    }
+    // get_surveys_v2 against a CDEC compatible CSVDataServlet at base_url
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_surveys_v2_from(
+        &self,
+        client: &Client,
+        base_url: &str,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        sensor_code: Option<u32>,
+        last_known_date: Option<&NaiveDate>,
+        max_retries: u32,
     ) -> Option<ObservableRange> {
-        let daily_observables = self.get_daily_surveys(client, start_date, end_date).await;
-        let monthly_observables = self.get_monthly_surveys(client, start_date, end_date).await;
+        let daily_observables = match self
+            .get_survey_general(
+                client,
+                base_url,
+                start_date,
+                end_date,
+                "D",
+                sensor_code,
+                last_known_date,
+                max_retries,
+            )
+            .await
+        {
+            Some(ConditionalBody::Modified(response_body)) => {
+                response_body.response_to_surveys(sensor_code)
+            }
+            Some(ConditionalBody::NotModified) => {
+                info!(
+                    "skipping {}, unchanged since {:?}",
+                    self.dam, last_known_date
+                );
+                return None;
+            }
+            None => None,
+        };
+        let monthly_observables = match self
+            .get_survey_general(
+                client,
+                base_url,
+                start_date,
+                end_date,
+                "M",
+                sensor_code,
+                last_known_date,
+                max_retries,
+            )
+            .await
+        {
+            Some(ConditionalBody::Modified(response_body)) => {
+                response_body.response_to_surveys(sensor_code)
+            }
+            _ => None,
+        };
         match (daily_observables, monthly_observables) {
             (Some(mut daily), Some(monthly)) => {
                 for survey in monthly.observations {
@@ -207,8 +486,12 @@ impl Reservoir {
         let daily_url = format!("http://cdec.water.ca.gov/dynamicapp/req/CSVDataServlet?Stations={}&SensorNums=15&dur_code={}&Start={}&End={}", self.station_id.as_str(), daily_rate, start_date_str, end_date_str);
         let daily_response = client.get(daily_url).send().await.unwrap();
         let daily_response_body = daily_response.text().await.unwrap();
-        let mut daily_observation_range = daily_response_body.response_to_surveys().unwrap();
-        let monthly_observation_range = monthly_response_body.response_to_surveys().unwrap();
+        let mut daily_observation_range = daily_response_body
+            .response_to_surveys(Some(STORAGE_SENSOR_CODE))
+            .unwrap();
+        let monthly_observation_range = monthly_response_body
+            .response_to_surveys(Some(STORAGE_SENSOR_CODE))
+            .unwrap();
         // insert the monthlys with update into daily
         for survey in monthly_observation_range.observations {
             daily_observation_range.update(survey);
@@ -218,7 +501,7 @@ impl Reservoir {
     }
     // collects reservoir information from https://raw.githubusercontent.com/afbase/california-water/main/obj/capacity.csv
     pub fn get_reservoir_vector() -> Vec<Reservoir> {
-        if let Ok(r) = Reservoir::parse_reservoir_csv(CSV_OBJECT) {
+        if let Ok(r) = Reservoir::parse_reservoir_csv(CSV_OBJECT, &LoaderOptions::default()) {
             r
         } else {
             panic!("failed to parse csv file")
@@ -226,13 +509,171 @@ impl Reservoir {
     }
     // gives option to select reservoir from a list of choices
     pub fn get_reservoir_vector_v2(reservoir: &str) -> Vec<Reservoir> {
-        if let Ok(r) = Reservoir::parse_reservoir_csv(reservoir) {
+        Reservoir::get_reservoir_vector_with_options(reservoir, &LoaderOptions::default())
+    }
+    // same as get_reservoir_vector_v2 for csv that isn't comma delimited
+    pub fn get_reservoir_vector_with_options(
+        reservoir: &str,
+        options: &LoaderOptions,
+    ) -> Vec<Reservoir> {
+        if let Ok(r) = Reservoir::parse_reservoir_csv(reservoir, options) {
             r
         } else {
             panic!("failed to parse csv file")
         }
     }
 
+    // fills in missing latitude/longitude from the CDEC station metadata,
+    // with at most `concurrency` requests in flight
+    pub async fn with_station_coordinates(
+        reservoirs: Vec<Reservoir>,
+        client: &Client,
+        max_retries: u32,
+        concurrency: usize,
+    ) -> Vec<Reservoir> {
+        stream::iter(reservoirs)
+            .map(|mut reservoir| async move {
+                if reservoir.latitude.is_none() || reservoir.longitude.is_none() {
+                    let url = format!("{}{}", STATION_METADATA_URL, reservoir.station_id);
+                    if let Some((latitude, longitude)) = get_station_coordinates(
+                        client,
+                        url.as_str(),
+                        reservoir.dam.as_str(),
+                        max_retries,
+                        INITIAL_BACKOFF_MILLIS,
+                    )
+                    .await
+                    {
+                        reservoir.latitude = Some(latitude);
+                        reservoir.longitude = Some(longitude);
+                    }
+                }
+                reservoir
+            })
+            .buffered(concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    // writes the reservoirs with known coordinates as a GeoJSON FeatureCollection of points
+    pub fn to_geojson(reservoirs: &[Reservoir]) -> String {
+        let features = reservoirs
+            .iter()
+            .filter_map(
+                |reservoir| match (reservoir.longitude, reservoir.latitude) {
+                    (Some(longitude), Some(latitude)) => Some(json!({
+                        "type": "Feature",
+                        "geometry": {
+                            "type": "Point",
+                            "coordinates": [longitude, latitude]
+                        },
+                        "properties": {
+                            "station_id": reservoir.station_id,
+                            "dam": reservoir.dam,
+                            "lake": reservoir.lake,
+                            "stream": reservoir.stream,
+                            "capacity": reservoir.capacity,
+                            "fill_year": reservoir.fill_year
+                        }
+                    })),
+                    _ => None,
+                },
+            )
+            .collect::<Vec<Value>>();
+        let feature_collection = json!({
+            "type": "FeatureCollection",
+            "features": features
+        });
+        feature_collection.to_string()
+    }
+
+    // case-insensitive search over dam, lake and stream names, best matches first:
+    // whole name, then name prefix, then word prefix, then substring, then a
+    // word one typo away from the query
+    pub fn find_by_name_fuzzy(reservoirs: &[Reservoir], query: &str) -> Vec<Reservoir> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut ranked = reservoirs
+            .iter()
+            .filter_map(|reservoir| {
+                [&reservoir.dam, &reservoir.lake, &reservoir.stream]
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(field, name)| {
+                        Reservoir::name_match_rank(name, query.as_str()).map(|rank| (rank, field))
+                    })
+                    .min()
+                    .map(|rank| (rank, reservoir))
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by_key(|(rank, _reservoir)| *rank);
+        ranked
+            .into_iter()
+            .map(|(_rank, reservoir)| reservoir.clone())
+            .collect()
+    }
+
+    fn name_match_rank(name: &str, query: &str) -> Option<u8> {
+        let name = name.trim().to_lowercase();
+        let mut words = name.split_whitespace();
+        if name == query {
+            Some(0)
+        } else if name.starts_with(query) {
+            Some(1)
+        } else if words.clone().any(|word| word.starts_with(query)) {
+            Some(2)
+        } else if name.contains(query) {
+            Some(3)
+        } else if query.chars().count() >= 4
+            && words.any(|word| Reservoir::within_one_edit(word, query))
+        {
+            Some(4)
+        } else {
+            None
+        }
+    }
+
+    fn within_one_edit(a: &str, b: &str) -> bool {
+        let a = a.chars().collect::<Vec<_>>();
+        let b = b.chars().collect::<Vec<_>>();
+        let (shorter, longer) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+        if longer.len() - shorter.len() > 1 {
+            return false;
+        }
+        let prefix = shorter
+            .iter()
+            .zip(longer.iter())
+            .take_while(|(x, y)| x == y)
+            .count();
+        if prefix == shorter.len() {
+            true
+        } else if shorter.len() == longer.len() {
+            // one substitution
+            shorter[prefix + 1..] == longer[prefix + 1..]
+        } else {
+            // one insertion
+            shorter[prefix..] == longer[prefix + 1..]
+        }
+    }
+
+    // groups reservoirs by the stream they sit on, blank streams go under "Unknown"
+    pub fn by_stream(reservoirs: &[Reservoir]) -> HashMap<String, Vec<Reservoir>> {
+        let mut streams: HashMap<String, Vec<Reservoir>> = HashMap::new();
+        for reservoir in reservoirs {
+            let stream = match reservoir.stream.trim() {
+                "" => UNKNOWN_STREAM,
+                stream => stream,
+            };
+            streams
+                .entry(stream.to_string())
+                .or_default()
+                .push(reservoir.clone());
+        }
+        streams
+    }
+
     fn parse_int(ess: &str) -> i32 {
         let ess_lowered = ess.trim().to_lowercase();
         let ess_lowered_str = ess_lowered.as_str();
@@ -245,16 +686,27 @@ impl Reservoir {
         }
     }
 
-    fn parse_reservoir_csv(csv_object: &str) -> Result<Vec<Reservoir>, std::io::Error> {
+    fn parse_coordinate(ess: &str) -> Option<f64> {
+        ess.trim().parse::<f64>().ok()
+    }
+
+    fn parse_reservoir_csv(
+        csv_object: &str,
+        options: &LoaderOptions,
+    ) -> Result<Vec<Reservoir>, std::io::Error> {
         let mut reservoir_list: Vec<Reservoir> = Vec::new();
         let mut rdr = ReaderBuilder::new()
-            .delimiter(b',')
+            .delimiter(options.delimiter)
             .has_headers(true)
             .from_reader(csv_object.as_bytes());
         for row in rdr.records() {
             let rho = row?;
             let capacity = Reservoir::parse_int(rho.get(4).unwrap_or_else(get_default_capacity));
             let fill_year = Reservoir::parse_int(rho.get(5).unwrap_or_else(get_default_year));
+            // LATITUDE and LONGITUDE columns are optional, the bundled capacity csvs
+            // leave them out and they come from the CDEC station metadata instead
+            let latitude = rho.get(6).and_then(Reservoir::parse_coordinate);
+            let longitude = rho.get(7).and_then(Reservoir::parse_coordinate);
             let reservoir = Reservoir {
                 station_id: String::from(rho.get(0).expect("station_id parse fail")),
                 dam: String::from(rho.get(1).expect("damn parse fail")),
@@ -262,6 +714,8 @@ impl Reservoir {
                 stream: String::from(rho.get(3).expect("stream parse fail")),
                 capacity,
                 fill_year,
+                latitude,
+                longitude,
             };
             reservoir_list.push(reservoir);
         }
@@ -271,11 +725,321 @@ impl Reservoir {
 
 #[cfg(test)]
 mod tests {
-    use crate::reservoir::Reservoir;
+    use crate::loader::LoaderOptions;
+    use crate::reservoir::{parse_station_metadata, Reservoir, UNKNOWN_STREAM};
+    use serde_json::Value;
+    #[cfg(not(target_family = "wasm"))]
+    use {
+        super::{
+            backoff, get_if_modified_since, get_station_coordinates, get_with_retries,
+            ConditionalBody,
+        },
+        crate::observation::STORAGE_SENSOR_CODE,
+        chrono::NaiveDate,
+        reqwest::Client,
+        std::{
+            io::{Read, Write},
+            net::TcpListener,
+            thread,
+            time::Duration,
+        },
+        tokio::time::Instant,
+    };
 
     #[test]
     fn test_reservoir_vector() {
         let reservoirs: Vec<Reservoir> = Reservoir::get_reservoir_vector();
         assert_eq!(reservoirs.len(), 218);
     }
+
+    #[test]
+    fn test_find_by_name_fuzzy() {
+        let reservoirs: Vec<Reservoir> = Reservoir::get_reservoir_vector();
+        let matches = Reservoir::find_by_name_fuzzy(&reservoirs, "shast");
+        // Shasta dam, and Lake Shastina on the Shasta River
+        assert_eq!(matches[0].station_id, "SHA");
+        assert!(matches.len() > 1);
+        assert!(matches
+            .iter()
+            .any(|reservoir| reservoir.station_id == "DRE"));
+        let matches = Reservoir::find_by_name_fuzzy(&reservoirs, "Shsta");
+        assert_eq!(matches[0].station_id, "SHA");
+        assert!(Reservoir::find_by_name_fuzzy(&reservoirs, "zzzz").is_empty());
+    }
+
+    #[test]
+    fn test_by_stream() {
+        let reservoirs: Vec<Reservoir> = Reservoir::get_reservoir_vector();
+        let streams = Reservoir::by_stream(&reservoirs);
+        let sacramento_river = streams
+            .get("Sacramento River")
+            .unwrap()
+            .iter()
+            .map(|reservoir| reservoir.station_id.as_str())
+            .collect::<Vec<_>>();
+        assert!(sacramento_river.contains(&"SHA"));
+        assert!(sacramento_river.contains(&"KES"));
+        let grouped = streams.values().map(|group| group.len()).sum::<usize>();
+        assert_eq!(grouped, reservoirs.len());
+        let csv_object = "ID,DAM,LAKE,STREAM,CAPACITY (AF),YEAR FILL
+AAA,Dam A,Lake A,  ,100,1950
+BBB,Dam B,Lake B,,200,1960
+";
+        let reservoirs = Reservoir::get_reservoir_vector_v2(csv_object);
+        let streams = Reservoir::by_stream(&reservoirs);
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams.get(UNKNOWN_STREAM).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_reservoir_vector_with_options() {
+        let comma_delimited = "ID,DAM,LAKE,STREAM,CAPACITY (AF),YEAR FILL
+SHA,Shasta,Lake Shasta,Sacramento River,4552000,1954
+ORO,Oroville,Lake Oroville,Feather River,3537577,1969
+";
+        let expected = Reservoir::get_reservoir_vector_v2(comma_delimited);
+        assert_eq!(expected.len(), 2);
+        let tab_delimited = comma_delimited.replace(',', "\t");
+        let actual = Reservoir::get_reservoir_vector_with_options(
+            tab_delimited.as_str(),
+            &LoaderOptions::tab_delimited(),
+        );
+        assert_eq!(actual, expected);
+        let pipe_delimited = comma_delimited.replace(',', "|");
+        let actual = Reservoir::get_reservoir_vector_with_options(
+            pipe_delimited.as_str(),
+            &LoaderOptions::pipe_delimited(),
+        );
+        assert_eq!(actual, expected);
+    }
+
+    // shaped like the table on https://cdec.water.ca.gov/dynamicapp/staMeta?station_id=SHA
+    const SHA_STATION_METADATA: &str = "<table><tr><td><b>Station ID</b></td><td>SHA</td>\
+<td><b>Elevation</b></td><td>1,067' ft</td></tr><tr><td><b>River Basin</b></td>\
+<td>SACRAMENTO R</td><td><b>County</b></td><td>SHASTA</td></tr><tr><td><b>Latitude</b></td>\
+<td>40.718000&#176</td><td><b>Longitude</b></td><td>-122.420000&#176</td></tr></table>";
+
+    #[test]
+    fn test_parse_station_metadata() {
+        assert_eq!(
+            parse_station_metadata(SHA_STATION_METADATA),
+            Some((40.718, -122.42))
+        );
+        assert_eq!(parse_station_metadata("<html>no such station</html>"), None);
+    }
+
+    #[test]
+    fn test_reservoirs_to_geojson() {
+        let mut reservoirs: Vec<Reservoir> = Reservoir::get_reservoir_vector();
+        // the bundled capacity csv has no coordinates of its own
+        let geojson: Value =
+            serde_json::from_str(Reservoir::to_geojson(&reservoirs).as_str()).unwrap();
+        assert_eq!(geojson["type"], "FeatureCollection");
+        assert!(geojson["features"].as_array().unwrap().is_empty());
+        let (latitude, longitude) = parse_station_metadata(SHA_STATION_METADATA).unwrap();
+        let shasta = reservoirs
+            .iter_mut()
+            .find(|reservoir| reservoir.station_id == "SHA")
+            .unwrap();
+        shasta.latitude = Some(latitude);
+        shasta.longitude = Some(longitude);
+        let geojson: Value =
+            serde_json::from_str(Reservoir::to_geojson(&reservoirs).as_str()).unwrap();
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["type"], "Feature");
+        assert_eq!(features[0]["geometry"]["type"], "Point");
+        assert_eq!(features[0]["geometry"]["coordinates"][0], -122.42);
+        assert_eq!(features[0]["geometry"]["coordinates"][1], 40.718);
+        assert_eq!(features[0]["properties"]["station_id"], "SHA");
+        assert_eq!(features[0]["properties"]["lake"], "Lake Shasta");
+        assert_eq!(features[0]["properties"]["capacity"], 4552000);
+        assert_eq!(features[0]["properties"]["fill_year"], 1954);
+    }
+
+    #[test]
+    fn test_reservoir_vector_with_coordinate_columns() {
+        let csv_object = "ID,DAM,LAKE,STREAM,CAPACITY (AF),YEAR FILL,LATITUDE,LONGITUDE
+ORO,Oroville,Lake Oroville,Feather River,3537577,1969,39.54,-121.493
+VIL,Vail,Vail Reservoir,Temecula Creek,51000,,,
+";
+        let reservoirs = Reservoir::get_reservoir_vector_v2(csv_object);
+        assert_eq!(reservoirs[0].latitude, Some(39.54));
+        assert_eq!(reservoirs[0].longitude, Some(-121.493));
+        assert_eq!(reservoirs[1].latitude, None);
+        assert_eq!(reservoirs[1].longitude, None);
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[tokio::test]
+    async fn test_get_station_coordinates() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0u8; 1024];
+            let _ = stream.read(&mut buffer).unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                SHA_STATION_METADATA.len(),
+                SHA_STATION_METADATA
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        let client = Client::new();
+        let actual = get_station_coordinates(&client, url.as_str(), "Shasta", 0, 1).await;
+        server.join().unwrap();
+        assert_eq!(actual, Some((40.718, -122.42)));
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[tokio::test(start_paused = true)]
+    async fn test_backoff_does_not_block() {
+        // on a single threaded runtime two backoffs overlap instead of running back to back
+        let started = Instant::now();
+        tokio::join!(
+            backoff(Duration::from_millis(200)),
+            backoff(Duration::from_millis(200))
+        );
+        assert_eq!(started.elapsed(), Duration::from_millis(200));
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[tokio::test]
+    async fn test_get_with_retries() {
+        // a server that fails twice then answers with a csv body
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for request_count in 0..3 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buffer = [0u8; 1024];
+                let _ = stream.read(&mut buffer).unwrap();
+                let response = if request_count < 2 {
+                    "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                } else {
+                    let body = "STATION_ID,DURATION\r\nVIL,D\r\n";
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let client = Client::new();
+        let actual = get_with_retries(&client, url.as_str(), "Vail", 2, 1).await;
+        server.join().unwrap();
+        assert_eq!(
+            actual,
+            Some(String::from("STATION_ID,DURATION\r\nVIL,D\r\n"))
+        );
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[tokio::test]
+    async fn test_get_with_retries_gives_up() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buffer = [0u8; 1024];
+                let _ = stream.read(&mut buffer).unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .unwrap();
+            }
+        });
+        let client = Client::new();
+        let actual = get_with_retries(&client, url.as_str(), "Vail", 1, 1).await;
+        server.join().unwrap();
+        assert_eq!(actual, None);
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[tokio::test]
+    async fn test_get_if_modified_since_not_modified() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0u8; 1024];
+            let read = stream.read(&mut buffer).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&buffer[..read]).to_lowercase()
+        });
+        let client = Client::new();
+        let last_known_date = NaiveDate::from_ymd_opt(2022, 1, 1).unwrap();
+        let actual =
+            get_if_modified_since(&client, url.as_str(), "Vail", &last_known_date, 2, 1).await;
+        let request = server.join().unwrap();
+        assert_eq!(actual, Some(ConditionalBody::NotModified));
+        assert!(request.contains("if-modified-since: sat, 01 jan 2022 00:00:00 gmt"));
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[tokio::test]
+    async fn test_get_surveys_v2_from_reads_one_sensor() {
+        // a station answering with storage and elevation rows for its dailies
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut request_lines = Vec::new();
+            for body in [
+                "STATION_ID,DURATION,SENSOR_NUMBER,SENSOR_TYPE,DATE TIME,OBS DATE,VALUE,DATA_FLAG,UNITS\r\n\
+                 VIL,D,15,STORAGE,20220215 0000,20220215 0000,9593, ,AF\r\n\
+                 VIL,D,6,RES ELE,20220215 0000,20220215 0000,1470, ,FEET\r\n\
+                 VIL,D,15,STORAGE,20220216 0000,20220216 0000,9589, ,AF\r\n",
+                "STATION_ID,DURATION,SENSOR_NUMBER,SENSOR_TYPE,DATE TIME,OBS DATE,VALUE,DATA_FLAG,UNITS\r\n",
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buffer = [0u8; 1024];
+                let read = stream.read(&mut buffer).unwrap();
+                let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+                request_lines.push(request.lines().next().unwrap().to_string());
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            request_lines
+        });
+        let reservoir = Reservoir::get_reservoir_vector_v2(
+            "ID,DAM,LAKE,STREAM,CAPACITY (AF),YEAR FILL
+VIL,Vail,Vail Reservoir,Temecula Creek,51000,
+",
+        )
+        .remove(0);
+        let start_date = NaiveDate::from_ymd_opt(2022, 2, 15).unwrap();
+        let end_date = NaiveDate::from_ymd_opt(2022, 2, 16).unwrap();
+        let actual = reservoir
+            .get_surveys_v2_from(
+                &Client::new(),
+                url.as_str(),
+                &start_date,
+                &end_date,
+                Some(STORAGE_SENSOR_CODE),
+                None,
+                0,
+            )
+            .await
+            .unwrap();
+        let request_lines = server.join().unwrap();
+        for request_line in &request_lines {
+            assert!(request_line.contains("SensorNums=15&"));
+        }
+        let values = actual
+            .observations
+            .iter()
+            .map(|survey| survey.get_value())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![9593.0, 9589.0]);
+    }
 }
@@ -1,6 +1,6 @@
 use crate::{
     observable::{MonthDatum, Observable},
-    observation::{DataRecording, Duration, Observation},
+    observation::{DataRecording, Duration, Observation, STORAGE_SENSOR_CODE, STORAGE_UNIT},
 };
 use chrono::NaiveDate;
 use csv::StringRecord;
@@ -90,6 +90,7 @@ impl Observable for Observation {
 
 pub trait Interpolate {
     fn interpolate_pair(self) -> Option<Vec<Survey>>;
+    fn interpolate_pair_evap(self, daily_loss_af: f64) -> Option<Vec<Survey>>;
 }
 
 impl From<Observation> for Survey {
@@ -120,6 +121,8 @@ impl std::convert::From<Survey> for Observation {
                 date_recording: t.date_recording,
                 value: t.value,
                 duration: Duration::Daily,
+                sensor_code: STORAGE_SENSOR_CODE,
+                unit: STORAGE_UNIT.to_string(),
             },
             Survey::Monthly(t) => Observation {
                 station_id: t.station_id,
@@ -127,6 +130,8 @@ impl std::convert::From<Survey> for Observation {
                 date_recording: t.date_recording,
                 value: t.value,
                 duration: Duration::Monthly,
+                sensor_code: STORAGE_SENSOR_CODE,
+                unit: STORAGE_UNIT.to_string(),
             },
         }
     }
@@ -277,6 +282,14 @@ impl Hash for Tap {
 
 impl Interpolate for (Survey, Survey) {
     fn interpolate_pair(self) -> Option<Vec<Survey>> {
+        self.interpolate_pair_evap(0.0)
+    }
+
+    // like interpolate_pair, but subtracts an estimated evaporative loss of
+    // daily_loss_af per day. the loss is taken relative to the straight line
+    // so both observed endpoints are kept and the curve sags in between,
+    // by daily_loss_af * days / 4 at its deepest in the middle of the gap
+    fn interpolate_pair_evap(self, daily_loss_af: f64) -> Option<Vec<Survey>> {
         let start = self.0.clone();
         let start_tap = start.get_tap();
         let end = self.1;
@@ -305,7 +318,8 @@ impl Interpolate for (Survey, Survey) {
         let slope = (y_n - y_0) / (x_n - x_0);
         for idx in 1..=days {
             let fdx: f64 = idx.cast();
-            let y_i = (slope * (fdx - x_0) + y_0).round();
+            let evaporation = daily_loss_af * (fdx - x_0) * (x_n - fdx) / (x_n - x_0);
+            let y_i = (slope * (fdx - x_0) + y_0 - evaporation).round().max(0.0);
             let value = y_i as u32;
             let recording = DataRecording::Recording(value);
             let date = start_obs.date_observation + chrono::Duration::try_days(idx).unwrap();
@@ -419,7 +433,9 @@ impl Hash for Survey {
 #[cfg(test)]
 mod test {
     use super::{Interpolate, Survey, Tap};
-    use crate::observation::{DataRecording, Duration, Observation};
+    use crate::observation::{
+        DataRecording, Duration, Observation, STORAGE_SENSOR_CODE, STORAGE_UNIT,
+    };
     use chrono::NaiveDate;
     use csv::StringRecord;
 
@@ -497,6 +513,8 @@ mod test {
             date_recording,
             value,
             duration: Duration::Daily,
+            sensor_code: STORAGE_SENSOR_CODE,
+            unit: STORAGE_UNIT.to_string(),
         };
         let observation_1 = Observation {
             station_id,
@@ -504,6 +522,8 @@ mod test {
             date_recording,
             value,
             duration: Duration::Monthly,
+            sensor_code: STORAGE_SENSOR_CODE,
+            unit: STORAGE_UNIT.to_string(),
         };
         let actual_0: Observation = survey_0.into();
         let actual_1: Observation = survey_1.into();
@@ -535,6 +555,8 @@ mod test {
             date_recording,
             value,
             duration: Duration::Daily,
+            sensor_code: STORAGE_SENSOR_CODE,
+            unit: STORAGE_UNIT.to_string(),
         };
         let observation_1 = Observation {
             station_id,
@@ -542,6 +564,8 @@ mod test {
             date_recording,
             value,
             duration: Duration::Monthly,
+            sensor_code: STORAGE_SENSOR_CODE,
+            unit: STORAGE_UNIT.to_string(),
         };
         let actual_0: Survey = observation_0.into();
         let actual_1: Survey = observation_1.into();
@@ -685,4 +709,36 @@ mod test {
         });
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn interpolate_a_pair_evap() {
+        let date_0 = NaiveDate::from_ymd_opt(2022, 6, 1).unwrap();
+        let date_1 = NaiveDate::from_ymd_opt(2022, 9, 1).unwrap();
+        let start = Survey::Daily(Tap {
+            station_id: String::new(),
+            date_observation: date_0,
+            date_recording: date_0,
+            value: DataRecording::Recording(100000),
+        });
+        let end = Survey::Daily(Tap {
+            station_id: String::new(),
+            date_observation: date_1,
+            date_recording: date_1,
+            value: DataRecording::Recording(80000),
+        });
+        let linear = (start.clone(), end.clone()).interpolate_pair().unwrap();
+        let evaporated = (start, end).interpolate_pair_evap(50.0).unwrap();
+        assert_eq!(linear.len(), evaporated.len());
+        // the observed endpoints are kept
+        assert_eq!(evaporated.first().unwrap().get_value(), 100000.0);
+        assert_eq!(evaporated.last().unwrap().get_value(), 80000.0);
+        let last = linear.len() - 1;
+        for (linear_survey, evaporated_survey) in linear[1..last].iter().zip(&evaporated[1..last]) {
+            assert_eq!(
+                linear_survey.date_observation(),
+                evaporated_survey.date_observation()
+            );
+            assert!(evaporated_survey.get_value() < linear_survey.get_value());
+        }
+    }
 }
@@ -1,20 +1,32 @@
+use crate::observation::DataRecording;
 use crate::observation::Observation;
 use crate::reservoir::Reservoir;
-use crate::survey::{CompressedStringRecord, VectorCompressedStringRecord};
+use crate::survey::{CompressedStringRecord, Tap, VectorCompressedStringRecord};
 use crate::{
     normalized_naive_date::NormalizedNaiveDate, observable::ObservableRange, survey::Survey,
 };
-use chrono::{DateTime, Datelike, Local, NaiveDate};
+use chrono::{Datelike, NaiveDate};
 use easy_cast::Cast;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering::{Equal, Greater, Less};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 pub const NUMBER_OF_CHARTS_TO_DISPLAY_DEFAULT: usize = 20;
+pub const STATEWIDE_STATION_ID: &str = "CA";
+// how far the first and last surveys may be from October 1 and September 30
+pub const WATER_YEAR_COMPLETENESS_TOLERANCE_DAYS: i64 = 7;
 
 /// California’s water year runs from October 1 to September 30 and is the official 12-month timeframe used by water managers to compile and compare hydrologic records.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct WaterYear(pub Vec<Survey>);
 
+// a survey value placed on the day of the normalized water year, so that
+// different years line up on the same x indices
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormalizedPoint {
+    pub day_of_water_year: usize,
+    pub value: f64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WaterYearStatistics {
     pub year: i32,
@@ -22,7 +34,205 @@ pub struct WaterYearStatistics {
     pub date_highest: NaiveDate,
     pub highest_value: f64,
     pub lowest_value: f64,
+    // older min/max outputs were written without these
+    #[serde(default)]
+    pub mean_value: f64,
+    #[serde(default)]
+    pub median_value: f64,
+    // the reservoir's capacity in acre-feet, when known
+    #[serde(default)]
+    pub capacity: Option<i32>,
+}
+
+impl WaterYearStatistics {
+    pub fn with_capacity(mut self, capacity: Option<i32>) -> Self {
+        self.capacity = capacity.filter(|capacity| *capacity > 0);
+        self
+    }
+
+    pub fn lowest_percent_of_capacity(&self) -> Option<f64> {
+        self.percent_of_capacity(self.lowest_value)
+    }
+
+    pub fn highest_percent_of_capacity(&self) -> Option<f64> {
+        self.percent_of_capacity(self.highest_value)
+    }
+
+    fn percent_of_capacity(&self, value: f64) -> Option<f64> {
+        self.capacity.map(|capacity| {
+            let capacity: f64 = capacity.cast();
+            value / capacity * 100.0
+        })
+    }
+}
+// how the driest water years are ranked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DriestMetric {
+    // the single lowest survey of the year
+    #[default]
+    Min,
+    // the average of the year's surveys
+    Mean,
+    // the storage at the end of September
+    EndOfYear,
+}
+
+impl DriestMetric {
+    fn value(&self, water_year: &WaterYear) -> f64 {
+        let surveys = &water_year.0;
+        match self {
+            DriestMetric::Min => surveys
+                .iter()
+                .map(|survey| survey.get_value())
+                .fold(f64::MAX, f64::min),
+            DriestMetric::Mean => {
+                let total = surveys.iter().map(|survey| survey.get_value()).sum::<f64>();
+                total / surveys.len() as f64
+            }
+            DriestMetric::EndOfYear => surveys
+                .iter()
+                .max()
+                .map(|survey| survey.get_value())
+                .unwrap_or(f64::MAX),
+        }
+    }
+}
+
+// columns a table of water year statistics can be ordered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatisticsSortKey {
+    #[default]
+    Year,
+    Lowest,
+    Highest,
+    Mean,
+    Median,
+}
+
+impl StatisticsSortKey {
+    fn value(&self, statistics: &WaterYearStatistics) -> f64 {
+        match self {
+            StatisticsSortKey::Year => statistics.year as f64,
+            StatisticsSortKey::Lowest => statistics.lowest_value,
+            StatisticsSortKey::Highest => statistics.highest_value,
+            StatisticsSortKey::Mean => statistics.mean_value,
+            StatisticsSortKey::Median => statistics.median_value,
+        }
+    }
+}
+
+// one page of statistics ordered by key, ties broken by year in the same
+// direction so paging through equal values is stable
+pub fn sort_water_year_statistics(
+    statistics: &[WaterYearStatistics],
+    key: StatisticsSortKey,
+    ascending: bool,
+    limit: usize,
+    offset: usize,
+) -> Vec<&WaterYearStatistics> {
+    let mut sorted = statistics.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| {
+        let ordering = key
+            .value(a)
+            .total_cmp(&key.value(b))
+            .then(a.year.cmp(&b.year));
+        if ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    });
+    sorted.into_iter().skip(offset).take(limit).collect()
+}
+
+// consecutive water years below a percent of normal storage
+#[derive(Debug, Clone, PartialEq)]
+pub struct DroughtRun {
+    pub start_year: i32,
+    pub end_year: i32,
+    // the shortfall below normal summed over the run, in percentage points
+    pub depth: f64,
+}
+
+// a year is dry when its mean storage is below threshold_pct of the mean
+// across all years; runs of two or more consecutive dry years are droughts
+pub fn drought_runs(statistics: &[WaterYearStatistics], threshold_pct: f64) -> Vec<DroughtRun> {
+    if statistics.is_empty() {
+        return Vec::new();
+    }
+    let normal =
+        statistics.iter().map(|stat| stat.mean_value).sum::<f64>() / statistics.len() as f64;
+    // statistics read from files written before means were kept have a mean of 0,
+    // and there is no normal to measure a drought against
+    if !normal.is_finite() || normal <= 0.0 {
+        return Vec::new();
+    }
+    let mut years = statistics
+        .iter()
+        .map(|stat| (stat.year, stat.mean_value / normal * 100.0))
+        .collect::<Vec<_>>();
+    years.sort_by_key(|(year, _)| *year);
+    let mut runs: Vec<DroughtRun> = Vec::new();
+    let mut dry_years: Vec<(i32, f64)> = Vec::new();
+    for (year, pct_of_normal) in years {
+        let continues_run = dry_years
+            .last()
+            .is_some_and(|(last_year, _)| *last_year + 1 == year);
+        if pct_of_normal >= threshold_pct || !continues_run {
+            push_drought_run(&mut runs, &dry_years);
+            dry_years.clear();
+        }
+        if pct_of_normal < threshold_pct {
+            dry_years.push((year, pct_of_normal));
+        }
+    }
+    push_drought_run(&mut runs, &dry_years);
+    runs
+}
+
+// a single dry year is not a drought
+fn push_drought_run(runs: &mut Vec<DroughtRun>, dry_years: &[(i32, f64)]) {
+    if let [(start_year, _), .., (end_year, _)] = dry_years {
+        runs.push(DroughtRun {
+            start_year: *start_year,
+            end_year: *end_year,
+            depth: dry_years
+                .iter()
+                .map(|(_, pct_of_normal)| 100.0 - pct_of_normal)
+                .sum(),
+        });
+    }
+}
+
+// the first date in a water year that storage reached the fill threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct YearFillEvent {
+    pub year: i32,
+    // None when the threshold was never reached that year
+    pub date: Option<NaiveDate>,
 }
+
+// per water year, the first date storage reached threshold_pct of capacity
+pub fn fill_events(
+    water_years: &[WaterYear],
+    capacity: i32,
+    threshold_pct: f64,
+) -> Vec<YearFillEvent> {
+    let capacity: f64 = capacity.cast();
+    let threshold = capacity * threshold_pct / 100.0;
+    let mut events = water_years
+        .iter()
+        .filter_map(|water_year| {
+            water_year.start_year().map(|year| YearFillEvent {
+                year,
+                date: water_year.first_date_at_or_above(threshold),
+            })
+        })
+        .collect::<Vec<_>>();
+    events.sort_by_key(|event| event.year);
+    events
+}
+
 pub trait NormalizeCalendarYear {
     fn normalize_calendar_years(&mut self);
 }
@@ -36,8 +246,12 @@ pub trait NormalizeWaterYears {
     fn get_largest_acrefeet_over_n_years(&self, len: usize) -> Result<f64, WaterYearErrors>;
     fn get_complete_normalized_water_years(&self) -> Self;
     fn sort_by_lowest_recorded_years(&mut self);
+    fn sort_by_driest(&mut self, metric: DriestMetric);
     fn sort_by_most_recent(&mut self);
     fn sort_surveys(&mut self);
+    fn normalized_points_of_years(&self, years: &[i32]) -> Vec<(i32, Vec<NormalizedPoint>)>;
+    fn most_recent_complete_water_year(&self) -> Option<i32>;
+    fn percentile_rank_of_year(&self, year: i32) -> Option<f64>;
 }
 
 impl NormalizeWaterYears for Vec<WaterYear> {
@@ -62,18 +276,7 @@ impl NormalizeWaterYears for Vec<WaterYear> {
                 // California’s water year runs from October 1 to September 30 and is the official 12-month timeframe
                 let month = tap.date_observation.month();
                 let day = tap.date_observation.day();
-                let year = {
-                    let dt: DateTime<Local> = Local::now();
-                    let (first_year, second_year) = {
-                        let this = &dt.naive_local().date();
-                        let year = this.year();
-                        (year - 1, year)
-                    };
-                    match month {
-                        10..=12 => first_year,
-                        _ => second_year,
-                    }
-                };
+                let year = NormalizedNaiveDate::derive_normalized_year(month);
                 match NaiveDate::from_ymd_opt(year, month, day) {
                     Some(d) => {
                         tap.date_observation = d;
@@ -127,29 +330,11 @@ impl NormalizeWaterYears for Vec<WaterYear> {
     }
 
     fn sort_by_lowest_recorded_years(&mut self) {
-        self.sort_by(|a, b| {
-            let a_surveys = &a.0;
-            let b_surveys = &b.0;
-            let a_min = {
-                let mut val = f64::MAX;
-                let mut other;
-                for survey in a_surveys {
-                    other = survey.get_value();
-                    val = val.min(other)
-                }
-                val
-            };
-            let b_min = {
-                let mut val = f64::MAX;
-                let mut other;
-                for survey in b_surveys {
-                    other = survey.get_value();
-                    val = val.min(other)
-                }
-                val
-            };
-            a_min.partial_cmp(&b_min).unwrap()
-        });
+        self.sort_by_driest(DriestMetric::Min);
+    }
+
+    fn sort_by_driest(&mut self, metric: DriestMetric) {
+        self.sort_by(|a, b| metric.value(a).total_cmp(&metric.value(b)));
     }
 
     fn sort_by_most_recent(&mut self) {
@@ -166,6 +351,54 @@ impl NormalizeWaterYears for Vec<WaterYear> {
         self.reverse();
     }
 
+    fn normalized_points_of_years(&self, years: &[i32]) -> Vec<(i32, Vec<NormalizedPoint>)> {
+        years
+            .iter()
+            .filter_map(|year| {
+                self.iter()
+                    .find(|water_year| water_year.start_year() == Some(*year))
+                    .map(|water_year| (*year, water_year.normalized_points()))
+            })
+            .collect()
+    }
+
+    // the latest start year whose water year passes WaterYear::is_complete,
+    // so a partial current year is never labelled as the most recent one
+    fn most_recent_complete_water_year(&self) -> Option<i32> {
+        self.iter()
+            .filter(|water_year| water_year.is_complete())
+            .filter_map(|water_year| water_year.start_year())
+            .max()
+    }
+
+    // where the year's peak storage falls among the other years, 0 to 100:
+    // the share of other years with a lower peak, ties counting as half
+    fn percentile_rank_of_year(&self, year: i32) -> Option<f64> {
+        let peak = |water_year: &WaterYear| {
+            water_year
+                .0
+                .iter()
+                .map(|survey| survey.get_value())
+                .fold(f64::MIN, f64::max)
+        };
+        let year_index = self
+            .iter()
+            .position(|water_year| water_year.start_year() == Some(year))?;
+        let year_peak = peak(&self[year_index]);
+        let others = self
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != year_index)
+            .map(|(_, water_year)| peak(water_year))
+            .collect::<Vec<_>>();
+        if others.is_empty() {
+            return Some(100.0);
+        }
+        let below = others.iter().filter(|other| **other < year_peak).count() as f64;
+        let tied = others.iter().filter(|other| **other == year_peak).count() as f64;
+        Some((below + tied / 2.0) / others.len() as f64 * 100.0)
+    }
+
     fn sort_surveys(&mut self) {
         for water_year in self {
             water_year.0.sort_by(|a, b| {
@@ -284,6 +517,104 @@ impl WaterYear {
         let last_date = last_survey.get_tap().date_recording;
         (first_date, last_date)
     }
+    // sums every reservoir's surveys by date into one statewide series and
+    // splits it into water years; the ranges should already be interpolated
+    // so each date has a value from every reservoir
+    pub fn statewide_water_years(observable_ranges: &[ObservableRange]) -> Vec<WaterYear> {
+        let mut statewide: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+        for observable_range in observable_ranges {
+            for survey in &observable_range.observations {
+                *statewide.entry(survey.date_observation()).or_default() += survey.get_value();
+            }
+        }
+        let surveys = statewide
+            .into_iter()
+            .map(|(date, value)| {
+                let value: u32 = value.round().cast();
+                Survey::Daily(Tap {
+                    station_id: STATEWIDE_STATION_ID.to_string(),
+                    date_observation: date,
+                    date_recording: date,
+                    value: DataRecording::Recording(value),
+                })
+            })
+            .collect::<Vec<_>>();
+        if surveys.is_empty() {
+            return Vec::new();
+        }
+        let statewide_range: ObservableRange = surveys.into();
+        WaterYear::water_years_from_observable_range(&statewide_range)
+    }
+
+    // lows and highs of each statewide water year, in year order; the driest
+    // and wettest years are the min and max since statistics order by lowest value
+    pub fn statewide_water_year_statistics(
+        observable_ranges: &[ObservableRange],
+    ) -> Vec<WaterYearStatistics> {
+        WaterYear::statewide_water_years(observable_ranges)
+            .iter()
+            .map(|water_year| water_year.into())
+            .collect()
+    }
+
+    // the calendar year the water year starts in, like WaterYearStatistics::year.
+    // date_recording keeps the original date once a water year is normalized
+    pub fn start_year(&self) -> Option<i32> {
+        self.0.iter().min().map(|survey| {
+            let date = survey.get_tap().date_recording;
+            match date.month() {
+                10..=12 => date.year(),
+                _ => date.year() - 1,
+            }
+        })
+    }
+
+    // the original date of the earliest survey at or above value
+    pub fn first_date_at_or_above(&self, value: f64) -> Option<NaiveDate> {
+        self.0
+            .iter()
+            .filter(|survey| survey.get_value() >= value)
+            .min()
+            .map(|survey| survey.get_tap().date_recording)
+    }
+
+    // the water year's surveys by day of water year, Feb 29 is left out
+    pub fn normalized_points(&self) -> Vec<NormalizedPoint> {
+        let mut points = self
+            .0
+            .iter()
+            .filter_map(|survey| {
+                NormalizedNaiveDate::from_date(survey.date_observation()).map(|date| {
+                    NormalizedPoint {
+                        day_of_water_year: date.day_of_water_year(),
+                        value: survey.get_value(),
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        points.sort_by_key(|point| point.day_of_water_year);
+        points
+    }
+
+    // true when the surveys reach from the start of October to the end of
+    // September, give or take WATER_YEAR_COMPLETENESS_TOLERANCE_DAYS
+    pub fn is_complete(&self) -> bool {
+        let (earliest, latest) = match (self.0.iter().min(), self.0.iter().max()) {
+            (Some(earliest), Some(latest)) => (
+                earliest.get_tap().date_observation,
+                latest.get_tap().date_observation,
+            ),
+            _ => return false,
+        };
+        let start_year = match earliest.month() {
+            10..=12 => earliest.year(),
+            _ => earliest.year() - 1,
+        };
+        let start_of_year = NaiveDate::from_ymd_opt(start_year, 10, 1).unwrap();
+        let end_of_year = NaiveDate::from_ymd_opt(start_year + 1, 9, 30).unwrap();
+        (earliest - start_of_year).num_days() <= WATER_YEAR_COMPLETENESS_TOLERANCE_DAYS
+            && (end_of_year - latest).num_days() <= WATER_YEAR_COMPLETENESS_TOLERANCE_DAYS
+    }
     pub fn calendar_year_change(&mut self) -> f64 {
         let _ = &self.0.sort();
         let first_day = self.0.first().unwrap();
@@ -377,6 +708,12 @@ impl From<WaterYear> for WaterYearStatistics {
             }
         };
         sort_by_values_ascending(&mut surveys);
+        let values = surveys
+            .iter()
+            .map(|survey| survey.get_value())
+            .collect::<Vec<_>>();
+        let mean_value = values.iter().sum::<f64>() / values.len() as f64;
+        let median_value = median_of_sorted_values(&values);
         surveys.reverse();
         let vec_len = surveys.len();
         let lowest = surveys[vec_len - 1].clone();
@@ -389,6 +726,9 @@ impl From<WaterYear> for WaterYearStatistics {
             date_highest: highest_tap.date_observation,
             highest_value: highest.get_value(),
             lowest_value: lowest.get_value(),
+            mean_value,
+            median_value,
+            capacity: None,
         }
     }
 }
@@ -412,6 +752,18 @@ fn sort_by_values_ascending(surveys: &mut [Survey]) {
     });
 }
 
+// values must already be sorted ascending, no values has a median of 0
+fn median_of_sorted_values(values: &[f64]) -> f64 {
+    let middle = values.len() / 2;
+    if values.is_empty() {
+        0.0
+    } else if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
 impl PartialOrd for WaterYearStatistics {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -425,6 +777,9 @@ impl PartialEq for WaterYearStatistics {
             && self.date_highest == other.date_highest
             && self.highest_value == other.highest_value
             && self.lowest_value == other.lowest_value
+            && self.mean_value == other.mean_value
+            && self.median_value == other.median_value
+            && self.capacity == other.capacity
     }
 }
 
@@ -444,14 +799,20 @@ impl Eq for WaterYearStatistics {}
 
 #[cfg(test)]
 mod tests {
-    use super::WaterYear;
+    use super::{
+        drought_runs, fill_events, median_of_sorted_values, sort_water_year_statistics,
+        DriestMetric, DroughtRun, NormalizeWaterYears, StatisticsSortKey, WaterYear,
+        WaterYearStatistics, YearFillEvent,
+    };
     use crate::date_range::DateRange;
+    use crate::normalized_naive_date::NormalizedNaiveDate;
     use crate::observable::MonthDatum;
     use crate::observable::ObservableRange;
     use crate::observation::DataRecording;
+    use crate::reservoir::Reservoir;
     use crate::survey::{Survey, Tap};
     use crate::water_year::NormalizeCalendarYear;
-    use chrono::{DateTime, Datelike, Local, NaiveDate};
+    use chrono::NaiveDate;
     use std::collections::HashSet;
     #[test]
     fn test_water_years_from_surveys() {
@@ -530,8 +891,7 @@ mod tests {
             water_year.normalize_calendar_years();
         }
         // make expected
-        let dt: DateTime<Local> = Local::now();
-        let first_year = dt.naive_local().date().year() - 1;
+        let first_year = NormalizedNaiveDate::derive_normalized_year(10);
         let last_year = first_year + 1;
         let first_date = NaiveDate::from_ymd_opt(first_year, start_month, start_day).unwrap();
         let last_date = NaiveDate::from_ymd_opt(last_year, end_month, end_day).unwrap();
@@ -583,8 +943,7 @@ mod tests {
             water_year.normalize_calendar_years();
         }
         // make expected
-        let dt: DateTime<Local> = Local::now();
-        let first_year = dt.naive_local().date().year() - 1;
+        let first_year = NormalizedNaiveDate::derive_normalized_year(10);
         let last_year = first_year + 1;
         let first_date = NaiveDate::from_ymd_opt(first_year, start_month, start_day).unwrap();
         let last_date = NaiveDate::from_ymd_opt(last_year, end_month, end_day).unwrap();
@@ -630,4 +989,401 @@ mod tests {
         }
         // assert_eq!(actual_water_years, expected_water_years);
     }
+    fn daily_water_year(start_date: NaiveDate, end_date: NaiveDate) -> WaterYear {
+        let surveys = DateRange(start_date, end_date)
+            .map(|day| {
+                Survey::Daily(Tap {
+                    station_id: String::new(),
+                    date_observation: day,
+                    date_recording: day,
+                    value: DataRecording::Recording(3),
+                })
+            })
+            .collect::<Vec<_>>();
+        WaterYear(surveys)
+    }
+    #[test]
+    fn test_fill_events() {
+        let filling_year = daily_water_year(
+            NaiveDate::from_ymd_opt(2016, 10, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2017, 9, 30).unwrap(),
+        );
+        let filling_year = WaterYear(
+            filling_year
+                .0
+                .into_iter()
+                .enumerate()
+                .map(|(i, mut survey)| {
+                    survey.tap().value = DataRecording::Recording(i as u32 * 10);
+                    survey
+                })
+                .collect(),
+        );
+        let dry_year = daily_water_year(
+            NaiveDate::from_ymd_opt(2014, 10, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2015, 9, 30).unwrap(),
+        );
+        // 90% of 2000 is 1800, reached on the 181st day of water year 2016
+        let events = fill_events(&[filling_year, dry_year], 2000, 90.0);
+        assert_eq!(
+            events,
+            vec![
+                YearFillEvent {
+                    year: 2014,
+                    date: None,
+                },
+                YearFillEvent {
+                    year: 2016,
+                    date: NaiveDate::from_ymd_opt(2017, 3, 30),
+                },
+            ]
+        );
+    }
+    #[test]
+    fn test_is_complete_full_year() {
+        let water_year = daily_water_year(
+            NaiveDate::from_ymd_opt(2021, 10, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 9, 30).unwrap(),
+        );
+        assert!(water_year.is_complete());
+    }
+    #[test]
+    fn test_is_complete_missing_september() {
+        let water_year = daily_water_year(
+            NaiveDate::from_ymd_opt(2021, 10, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 8, 31).unwrap(),
+        );
+        assert!(!water_year.is_complete());
+    }
+    #[test]
+    fn test_is_complete_missing_october() {
+        let water_year = daily_water_year(
+            NaiveDate::from_ymd_opt(2021, 11, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 9, 30).unwrap(),
+        );
+        assert!(!water_year.is_complete());
+    }
+    #[test]
+    fn test_water_year_statistics_mean_and_median() {
+        let values = [7u32, 1, 4, 10, 3];
+        let surveys = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let day = NaiveDate::from_ymd_opt(2021, 10, 1 + i as u32).unwrap();
+                Survey::Daily(Tap {
+                    station_id: String::new(),
+                    date_observation: day,
+                    date_recording: day,
+                    value: DataRecording::Recording(*value),
+                })
+            })
+            .collect::<Vec<_>>();
+        let water_year = WaterYear(surveys);
+        let statistics: WaterYearStatistics = (&water_year).into();
+        assert_eq!(statistics.mean_value, 5.0);
+        assert_eq!(statistics.median_value, 4.0);
+        assert_eq!(statistics.lowest_value, 1.0);
+        assert_eq!(statistics.highest_value, 10.0);
+        let mut even_surveys = water_year.0;
+        even_surveys.pop();
+        let statistics: WaterYearStatistics = WaterYear(even_surveys).into();
+        assert_eq!(statistics.mean_value, 5.5);
+        assert_eq!(statistics.median_value, 5.5);
+        assert_eq!(median_of_sorted_values(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_water_year_statistics_without_mean_and_median() {
+        // written before mean_value and median_value were added
+        let json = r#"{"year":2021,"date_lowest":"2021-10-02","date_highest":"2021-10-04","highest_value":10.0,"lowest_value":1.0}"#;
+        let statistics: WaterYearStatistics = serde_json::from_str(json).unwrap();
+        assert_eq!(statistics.year, 2021);
+        assert_eq!(statistics.mean_value, 0.0);
+        assert_eq!(statistics.median_value, 0.0);
+        assert_eq!(statistics.capacity, None);
+    }
+    #[test]
+    fn test_sort_by_driest_metrics() {
+        let water_year = |station_id: &str, values: [u32; 4]| {
+            let surveys = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    let day = NaiveDate::from_ymd_opt(2021, 10, 1 + i as u32).unwrap();
+                    Survey::Daily(Tap {
+                        station_id: station_id.to_string(),
+                        date_observation: day,
+                        date_recording: day,
+                        value: DataRecording::Recording(*value),
+                    })
+                })
+                .collect::<Vec<_>>();
+            WaterYear(surveys)
+        };
+        let water_years = vec![
+            // lowest single survey
+            water_year("MIN", [100, 1, 100, 100]),
+            // lowest average
+            water_year("MEAN", [20, 20, 20, 30]),
+            // lowest end of year storage
+            water_year("END", [90, 90, 90, 10]),
+        ];
+        let driest = |metric: DriestMetric| {
+            let mut sorted = water_years.clone();
+            sorted.sort_by_driest(metric);
+            sorted[0].0[0].get_tap().station_id.clone()
+        };
+        assert_eq!(driest(DriestMetric::Min), "MIN");
+        assert_eq!(driest(DriestMetric::Mean), "MEAN");
+        assert_eq!(driest(DriestMetric::EndOfYear), "END");
+    }
+    #[test]
+    fn test_normalized_points_of_years_align() {
+        let water_years = vec![
+            // includes Feb 29 2020
+            daily_water_year(
+                NaiveDate::from_ymd_opt(2019, 10, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2020, 9, 30).unwrap(),
+            ),
+            daily_water_year(
+                NaiveDate::from_ymd_opt(2020, 10, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2021, 9, 30).unwrap(),
+            ),
+            daily_water_year(
+                NaiveDate::from_ymd_opt(2021, 10, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2022, 9, 30).unwrap(),
+            ),
+        ];
+        let selected = water_years.normalized_points_of_years(&[2019, 2021, 1900]);
+        assert_eq!(selected.len(), 2);
+        let (year_a, points_a) = &selected[0];
+        let (year_b, points_b) = &selected[1];
+        assert_eq!((*year_a, *year_b), (2019, 2021));
+        assert_eq!(points_a.len(), 365);
+        assert_eq!(points_b.len(), 365);
+        for (point_a, point_b) in points_a.iter().zip(points_b.iter()) {
+            assert_eq!(point_a.day_of_water_year, point_b.day_of_water_year);
+        }
+        assert_eq!(points_a[0].day_of_water_year, 0);
+        assert_eq!(points_a[364].day_of_water_year, 364);
+    }
+    #[test]
+    fn test_statewide_water_year_statistics() {
+        let reservoir = |station_id: &str, values: [u32; 4]| {
+            // two surveys in each of the 2020 and 2021 water years
+            let dates = [
+                NaiveDate::from_ymd_opt(2020, 11, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2021, 5, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2021, 11, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2022, 5, 1).unwrap(),
+            ];
+            let surveys = dates
+                .iter()
+                .zip(values.iter())
+                .map(|(date, value)| {
+                    Survey::Daily(Tap {
+                        station_id: station_id.to_string(),
+                        date_observation: *date,
+                        date_recording: *date,
+                        value: DataRecording::Recording(*value),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let observable_range: ObservableRange = surveys.into();
+            observable_range
+        };
+        // statewide totals are 110 and 210 in 2020, 120 and 400 in 2021
+        let observable_ranges = vec![
+            reservoir("AAA", [100, 10, 60, 200]),
+            reservoir("BBB", [10, 200, 60, 200]),
+        ];
+        let statistics = WaterYear::statewide_water_year_statistics(&observable_ranges);
+        assert_eq!(statistics.len(), 2);
+        assert_eq!(statistics[0].year, 2020);
+        assert_eq!(statistics[0].lowest_value, 110.0);
+        assert_eq!(statistics[0].highest_value, 210.0);
+        assert_eq!(statistics[1].lowest_value, 120.0);
+        let driest = statistics.iter().min().unwrap();
+        assert_eq!(driest.year, 2020);
+        let wettest = statistics.iter().max().unwrap();
+        assert_eq!(wettest.year, 2021);
+    }
+    #[test]
+    fn test_most_recent_complete_water_year() {
+        let mut water_years = vec![
+            daily_water_year(
+                NaiveDate::from_ymd_opt(2020, 10, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2021, 9, 30).unwrap(),
+            ),
+            daily_water_year(
+                NaiveDate::from_ymd_opt(2021, 10, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2022, 9, 30).unwrap(),
+            ),
+            // the current water year, still in progress
+            daily_water_year(
+                NaiveDate::from_ymd_opt(2022, 10, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2023, 4, 15).unwrap(),
+            ),
+        ];
+        assert_eq!(water_years.most_recent_complete_water_year(), Some(2021));
+        water_years.clear();
+        assert_eq!(water_years.most_recent_complete_water_year(), None);
+    }
+    #[test]
+    fn test_percentile_rank_of_year() {
+        let water_year = |start_year: i32, peak: u32| {
+            let date = NaiveDate::from_ymd_opt(start_year + 1, 4, 1).unwrap();
+            WaterYear(vec![Survey::Daily(Tap {
+                station_id: String::new(),
+                date_observation: date,
+                date_recording: date,
+                value: DataRecording::Recording(peak),
+            })])
+        };
+        let water_years = vec![
+            water_year(2018, 300),
+            water_year(2019, 900),
+            water_year(2020, 100),
+            water_year(2021, 500),
+            water_year(2022, 700),
+        ];
+        assert_eq!(water_years.percentile_rank_of_year(2019), Some(100.0));
+        assert_eq!(water_years.percentile_rank_of_year(2020), Some(0.0));
+        assert_eq!(water_years.percentile_rank_of_year(2021), Some(50.0));
+        assert_eq!(water_years.percentile_rank_of_year(1999), None);
+    }
+    #[test]
+    fn test_sort_water_year_statistics() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        // (year, lowest, highest, mean, median)
+        let statistics = [
+            (2019, 300.0, 900.0, 600.0, 500.0),
+            (2020, 100.0, 700.0, 650.0, 450.0),
+            (2021, 200.0, 800.0, 550.0, 700.0),
+            (2022, 100.0, 950.0, 500.0, 600.0),
+        ]
+        .iter()
+        .map(
+            |(year, lowest_value, highest_value, mean_value, median_value)| WaterYearStatistics {
+                year: *year,
+                date_lowest: date,
+                date_highest: date,
+                highest_value: *highest_value,
+                lowest_value: *lowest_value,
+                mean_value: *mean_value,
+                median_value: *median_value,
+                capacity: None,
+            },
+        )
+        .collect::<Vec<_>>();
+        let years = |key: StatisticsSortKey, ascending: bool, limit: usize, offset: usize| {
+            sort_water_year_statistics(&statistics, key, ascending, limit, offset)
+                .iter()
+                .map(|statistic| statistic.year)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            years(StatisticsSortKey::Year, false, 10, 0),
+            vec![2022, 2021, 2020, 2019]
+        );
+        // 2020 and 2022 tie on lowest and fall back to year
+        assert_eq!(
+            years(StatisticsSortKey::Lowest, true, 10, 0),
+            vec![2020, 2022, 2021, 2019]
+        );
+        assert_eq!(
+            years(StatisticsSortKey::Highest, false, 10, 0),
+            vec![2022, 2019, 2021, 2020]
+        );
+        assert_eq!(
+            years(StatisticsSortKey::Mean, true, 10, 0),
+            vec![2022, 2021, 2019, 2020]
+        );
+        assert_eq!(
+            years(StatisticsSortKey::Median, false, 10, 0),
+            vec![2021, 2022, 2019, 2020]
+        );
+        assert_eq!(years(StatisticsSortKey::Mean, true, 2, 1), vec![2021, 2019]);
+        assert!(years(StatisticsSortKey::Mean, true, 2, 4).is_empty());
+    }
+    #[test]
+    fn test_drought_runs() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let statistics = [
+            100.0, 100.0, 50.0, 100.0, 100.0, 60.0, 40.0, 60.0, 100.0, 190.0,
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, mean_value)| WaterYearStatistics {
+            year: 2010 + i as i32,
+            date_lowest: date,
+            date_highest: date,
+            highest_value: *mean_value,
+            lowest_value: *mean_value,
+            mean_value: *mean_value,
+            median_value: *mean_value,
+            capacity: None,
+        })
+        .collect::<Vec<_>>();
+        // normal is 90, so 2012 alone is dry and 2015 through 2017 are a drought
+        let runs = drought_runs(&statistics, 75.0);
+        assert_eq!(runs.len(), 1);
+        let DroughtRun {
+            start_year,
+            end_year,
+            depth,
+        } = runs[0].clone();
+        assert_eq!((start_year, end_year), (2015, 2017));
+        let expected_depth = 3.0 * 100.0 - (60.0 + 40.0 + 60.0) / 90.0 * 100.0;
+        assert!((depth - expected_depth).abs() < 1e-9);
+    }
+    #[test]
+    fn test_drought_runs_without_a_normal() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let statistics = (2010..2015)
+            .map(|year| WaterYearStatistics {
+                year,
+                date_lowest: date,
+                date_highest: date,
+                highest_value: 100.0,
+                lowest_value: 50.0,
+                mean_value: 0.0,
+                median_value: 0.0,
+                capacity: None,
+            })
+            .collect::<Vec<_>>();
+        assert!(drought_runs(&statistics, 75.0).is_empty());
+        let mut statistics = statistics;
+        statistics[0].mean_value = f64::NAN;
+        assert!(drought_runs(&statistics, 75.0).is_empty());
+    }
+    #[test]
+    fn test_water_year_statistics_percent_of_capacity() {
+        let shasta = Reservoir::get_reservoir_vector()
+            .into_iter()
+            .find(|reservoir| reservoir.station_id == "SHA")
+            .unwrap();
+        let date = NaiveDate::from_ymd_opt(2022, 4, 1).unwrap();
+        let water_year = WaterYear(
+            [500000u32, 2276000]
+                .iter()
+                .map(|value| {
+                    Survey::Daily(Tap {
+                        station_id: shasta.station_id.clone(),
+                        date_observation: date,
+                        date_recording: date,
+                        value: DataRecording::Recording(*value),
+                    })
+                })
+                .collect(),
+        );
+        let statistics = WaterYearStatistics::from(&water_year);
+        assert_eq!(statistics.lowest_percent_of_capacity(), None);
+        let statistics = statistics.with_capacity(Some(shasta.capacity));
+        assert_eq!(statistics.capacity, Some(4552000));
+        let lowest = statistics.lowest_percent_of_capacity().unwrap();
+        assert!((lowest - 500000.0 / 4552000.0 * 100.0).abs() < 1e-9);
+        assert_eq!(statistics.highest_percent_of_capacity(), Some(50.0));
+    }
 }
@@ -1,7 +1,7 @@
 use clap::Parser;
-use cmd::{peruse::Peruse, query::Query, survey::Survey, Commands};
-use log::{info, LevelFilter};
-use my_log::MY_LOGGER;
+use cmd::{find::Find, peruse::Peruse, query::Query, survey::Survey, Commands};
+use log::info;
+use my_log::{format_from_env, level_from_env, MY_LOGGER};
 use utils::run::Run;
 
 #[derive(Parser)]
@@ -20,8 +20,11 @@ struct Cli {
 
 #[tokio::main]
 async fn main() {
+    let log_level = level_from_env();
+    MY_LOGGER.set_max_level(log_level);
+    MY_LOGGER.set_format(format_from_env());
     log::set_logger(&MY_LOGGER).unwrap();
-    log::set_max_level(LevelFilter::Info);
+    log::set_max_level(log_level);
     let args = Cli::parse();
 
     match args.command {
@@ -31,6 +34,13 @@ async fn main() {
             start_date,
             end_date,
             california_only,
+            geojson_output,
+            format,
+            max_retries,
+            concurrency,
+            fetches_per_second,
+            sensor_code,
+            source,
         }) => {
             let query = Query {
                 summation_output,
@@ -38,6 +48,13 @@ async fn main() {
                 start_date,
                 end_date,
                 california_only,
+                geojson_output,
+                format,
+                max_retries,
+                concurrency,
+                fetches_per_second,
+                sensor_code,
+                source,
             };
             info!("hello world");
             query.run().await;
@@ -65,6 +82,8 @@ async fn main() {
             min_max_output,
             start_date,
             end_date,
+            format,
+            tidy,
         }) => {
             let peruse = Peruse {
                 summation_output,
@@ -73,9 +92,15 @@ async fn main() {
                 min_max_output,
                 start_date,
                 end_date,
+                format,
+                tidy,
             };
             peruse.run().await;
         }
+        Some(Commands::Find { query, json }) => {
+            let find = Find { query, json };
+            find.run().await;
+        }
         None => panic!("must specify a subcommand!"),
     }
 }
@@ -0,0 +1,137 @@
+use cdec::observable::{CompressedSurveyBuilder, ObservableRange};
+use chrono::NaiveDate;
+use parquet::{
+    data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type},
+    errors::ParquetError,
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+use std::{io::Write, sync::Arc};
+
+// one row per observation; date is days since the unix epoch per the parquet DATE type
+const OBSERVATION_MESSAGE_TYPE: &str = "
+message observation {
+    REQUIRED BYTE_ARRAY station_id (UTF8);
+    REQUIRED INT32 date (DATE);
+    REQUIRED INT64 value;
+}
+";
+
+pub fn write_observations_parquet<W: Write + Send>(
+    observable_ranges: &[ObservableRange],
+    writer: W,
+) -> Result<(), ParquetError> {
+    let unix_epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+    let mut station_ids: Vec<ByteArray> = Vec::new();
+    let mut dates: Vec<i32> = Vec::new();
+    let mut values: Vec<i64> = Vec::new();
+    for observable_range in observable_ranges {
+        let mut observable_range = observable_range.clone();
+        // same rows as the csv reservoir output: recordings only, sorted by date
+        observable_range.retain();
+        observable_range.observations.sort();
+        for survey in observable_range.observations {
+            let tap = survey.get_tap();
+            station_ids.push(ByteArray::from(tap.station_id.as_str()));
+            dates.push((tap.date_observation - unix_epoch).num_days() as i32);
+            values.push(survey.get_value() as i64);
+        }
+    }
+    let schema = Arc::new(parse_message_type(OBSERVATION_MESSAGE_TYPE)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut file_writer = SerializedFileWriter::new(writer, schema, properties)?;
+    let mut row_group_writer = file_writer.next_row_group()?;
+    if let Some(mut column_writer) = row_group_writer.next_column()? {
+        column_writer
+            .typed::<ByteArrayType>()
+            .write_batch(&station_ids, None, None)?;
+        column_writer.close()?;
+    }
+    if let Some(mut column_writer) = row_group_writer.next_column()? {
+        column_writer
+            .typed::<Int32Type>()
+            .write_batch(&dates, None, None)?;
+        column_writer.close()?;
+    }
+    if let Some(mut column_writer) = row_group_writer.next_column()? {
+        column_writer
+            .typed::<Int64Type>()
+            .write_batch(&values, None, None)?;
+        column_writer.close()?;
+    }
+    row_group_writer.close()?;
+    file_writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::write_observations_parquet;
+    use cdec::{
+        observable::ObservableRange,
+        observation::DataRecording,
+        survey::{Survey, Tap},
+    };
+    use chrono::NaiveDate;
+    use parquet::{
+        file::reader::{FileReader, SerializedFileReader},
+        record::Field,
+    };
+
+    fn survey(station_id: &str, date: NaiveDate, value: DataRecording) -> Survey {
+        Survey::Daily(Tap {
+            station_id: String::from(station_id),
+            date_observation: date,
+            date_recording: date,
+            value,
+        })
+    }
+
+    #[test]
+    fn test_parquet_round_trip() {
+        let d_0 = NaiveDate::from_ymd_opt(2022, 2, 15).unwrap();
+        let d_1 = NaiveDate::from_ymd_opt(2022, 2, 16).unwrap();
+        let observable_ranges: Vec<ObservableRange> = vec![
+            vec![
+                survey("VIL", d_1, DataRecording::Recording(9589)),
+                survey("VIL", d_0, DataRecording::Recording(9593)),
+            ]
+            .into(),
+            vec![
+                survey("SHA", d_0, DataRecording::Recording(1838000)),
+                survey("SHA", d_1, DataRecording::Art),
+            ]
+            .into(),
+        ];
+        let file_path = std::env::temp_dir().join("cmd-test-parquet-round-trip.parquet");
+        let fs = std::fs::File::create(file_path.as_path()).unwrap();
+        write_observations_parquet(&observable_ranges, fs).unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&file_path).unwrap()).unwrap();
+        let unix_epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+        let actual = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                let row = row.unwrap();
+                let columns = row.get_column_iter().collect::<Vec<_>>();
+                match (columns[0].1, columns[1].1, columns[2].1) {
+                    (Field::Str(station_id), Field::Date(days), Field::Long(value)) => (
+                        station_id.clone(),
+                        unix_epoch + chrono::Duration::try_days(*days as i64).unwrap(),
+                        *value,
+                    ),
+                    _ => panic!("unexpected parquet row {row:?}"),
+                }
+            })
+            .collect::<Vec<_>>();
+        std::fs::remove_file(file_path).unwrap();
+        // the ART marker is not a recording and is dropped like in the csv output
+        let expected = vec![
+            (String::from("VIL"), d_0, 9593i64),
+            (String::from("VIL"), d_1, 9589i64),
+            (String::from("SHA"), d_0, 1838000i64),
+        ];
+        assert_eq!(actual, expected);
+    }
+}
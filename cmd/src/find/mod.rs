@@ -0,0 +1,79 @@
+use crate::Commands;
+use cdec::reservoir::Reservoir;
+use serde_json::json;
+use utils::{error::TryFromError, run::Run};
+
+pub struct Find {
+    // dam, lake or stream name to search for
+    pub query: String,
+    // print matches as json instead of a table
+    pub json: bool,
+}
+
+impl TryFrom<Commands> for Find {
+    type Error = TryFromError;
+
+    fn try_from(value: Commands) -> Result<Self, Self::Error> {
+        match value {
+            Commands::Find { query, json } => Ok(Find { query, json }),
+            _ => Err(TryFromError::FindError),
+        }
+    }
+}
+
+// station id, dam and capacity of each match, one reservoir per line
+pub fn reservoirs_table(reservoirs: &[Reservoir]) -> String {
+    let mut table = format!("{:<6}{:<32}{:>14}\n", "ID", "DAM", "CAPACITY (AF)");
+    for reservoir in reservoirs {
+        table.push_str(&format!(
+            "{:<6}{:<32}{:>14}\n",
+            reservoir.station_id, reservoir.dam, reservoir.capacity
+        ));
+    }
+    table
+}
+
+pub fn reservoirs_json(reservoirs: &[Reservoir]) -> String {
+    let matches = reservoirs
+        .iter()
+        .map(|reservoir| {
+            json!({
+                "station_id": reservoir.station_id,
+                "dam": reservoir.dam,
+                "capacity": reservoir.capacity,
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_string_pretty(&matches).unwrap()
+}
+
+impl Run for Find {
+    async fn run(self) {
+        let reservoirs =
+            Reservoir::find_by_name_fuzzy(&Reservoir::get_reservoir_vector(), &self.query);
+        if self.json {
+            println!("{}", reservoirs_json(&reservoirs));
+        } else {
+            print!("{}", reservoirs_table(&reservoirs));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{reservoirs_json, reservoirs_table};
+    use cdec::reservoir::Reservoir;
+    use serde_json::Value;
+
+    #[test]
+    fn test_oroville_matches_oro() {
+        let reservoirs =
+            Reservoir::find_by_name_fuzzy(&Reservoir::get_reservoir_vector(), "oroville");
+        assert_eq!(reservoirs[0].station_id, "ORO");
+        let table = reservoirs_table(&reservoirs[..1]);
+        assert!(table.lines().nth(1).unwrap().starts_with("ORO   Oroville"));
+        let json: Value = serde_json::from_str(&reservoirs_json(&reservoirs[..1])).unwrap();
+        assert_eq!(json[0]["station_id"], "ORO");
+        assert_eq!(json[0]["capacity"], reservoirs[0].capacity);
+    }
+}
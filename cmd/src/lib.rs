@@ -1,9 +1,40 @@
+#[cfg(feature = "parquet")]
+pub mod columnar;
+pub mod find;
 pub mod peruse;
 pub mod query;
 pub mod run;
 pub mod survey;
-use clap::Subcommand;
+use cdec::{observation::STORAGE_SENSOR_CODE, reservoir::DEFAULT_MAX_RETRIES};
+use clap::{Subcommand, ValueEnum};
+use run::{DEFAULT_CONCURRENCY, DEFAULT_FETCHES_PER_SECOND};
 use std::path::PathBuf;
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    // requires the `parquet` feature, hidden from --help and rejected at parsing without it
+    #[cfg_attr(not(feature = "parquet"), value(skip))]
+    Parquet,
+}
+
+// file format of the water year min/max output
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum MinMaxFormat {
+    // statistics keyed by station id, as read back by the charts
+    #[default]
+    Cbor,
+    // one record per reservoir water year for downstream tools
+    Json,
+}
+
+// where reservoirs and their surveys are fetched from
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Source {
+    #[default]
+    Cdec,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     Survey {
@@ -38,6 +69,27 @@ pub enum Commands {
         end_date: Option<String>,
         #[arg(long)]
         california_only: bool,
+        // output of reservoir locations as GeoJSON
+        #[arg(long, value_name = "GEOJSON_FILE")]
+        geojson_output: Option<PathBuf>,
+        // file format of the reservoir output
+        #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+        format: OutputFormat,
+        // retries after the first attempt of each CDEC request
+        #[arg(long, default_value_t = DEFAULT_MAX_RETRIES)]
+        max_retries: u32,
+        // number of reservoirs fetched from CDEC at once
+        #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+        concurrency: usize,
+        // reservoir fetches started each second, 0 is unlimited
+        #[arg(long, default_value_t = DEFAULT_FETCHES_PER_SECOND)]
+        fetches_per_second: u32,
+        // CDEC sensor the surveys are read from, 15 is storage
+        #[arg(long, default_value_t = STORAGE_SENSOR_CODE)]
+        sensor_code: u32,
+        // backend the reservoirs and surveys are fetched from
+        #[arg(long, value_enum, default_value_t = Source::Cdec)]
+        source: Source,
     },
     Peruse {
         // output of total reservoir capacity
@@ -58,5 +110,35 @@ pub enum Commands {
         // date of latest data to be collected
         #[arg(long, value_name = "YYYY-MM-DD")]
         end_date: Option<String>,
+        // file format of the min/max output
+        #[arg(long, value_enum, default_value_t = MinMaxFormat::Cbor)]
+        format: MinMaxFormat,
+        // write the water years output as station_id,water_year,low,high csv rows
+        #[arg(long)]
+        tidy: bool,
     },
+    Find {
+        // dam, lake or stream name to search for
+        query: String,
+        // print matches as json instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::OutputFormat;
+    use clap::ValueEnum;
+
+    #[test]
+    fn test_output_format_parquet_needs_feature() {
+        assert_eq!(OutputFormat::from_str("csv", true), Ok(OutputFormat::Csv));
+        let parquet = OutputFormat::from_str("parquet", true);
+        if cfg!(feature = "parquet") {
+            assert_eq!(parquet, Ok(OutputFormat::Parquet));
+        } else {
+            assert!(parquet.is_err());
+        }
+    }
 }
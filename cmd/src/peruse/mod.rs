@@ -1,5 +1,5 @@
 use crate::run::get_surveys_of_reservoirs;
-use crate::Commands;
+use crate::{Commands, MinMaxFormat};
 use cdec::{
     observable::{InterpolateObservableRanges, ObservableRangeRunner},
     reservoir::Reservoir,
@@ -8,11 +8,17 @@ use cdec::{
 };
 
 use chrono::{Local, NaiveDate};
+use csv::Writer;
 use log::info;
+use serde::{Deserialize, Serialize};
 use serde_cbor::to_writer;
 use std::collections::HashMap;
-use std::{io::Write, path::PathBuf};
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
 use utils::error::date_error;
+use utils::files::write_atomically;
 use utils::{error::TryFromError, run::Run};
 
 pub struct Peruse {
@@ -22,6 +28,117 @@ pub struct Peruse {
     pub min_max_output: Option<PathBuf>,
     pub start_date: Option<String>,
     pub end_date: Option<String>,
+    pub format: MinMaxFormat,
+    pub tidy: bool,
+}
+
+// a reservoir's lowest and highest storage in one water year
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct MinMaxRecord {
+    pub station_id: String,
+    pub year: i32,
+    pub min_value: f64,
+    pub min_date: NaiveDate,
+    pub max_value: f64,
+    pub max_date: NaiveDate,
+}
+
+// flattens the statistics into records sorted by station id then year
+pub fn min_max_records(
+    water_statistics: &HashMap<String, Vec<WaterYearStatistics>>,
+) -> Vec<MinMaxRecord> {
+    let mut records = water_statistics
+        .iter()
+        .flat_map(|(station_id, statistics)| {
+            statistics.iter().map(|statistic| MinMaxRecord {
+                station_id: station_id.clone(),
+                year: statistic.year,
+                min_value: statistic.lowest_value,
+                min_date: statistic.date_lowest,
+                max_value: statistic.highest_value,
+                max_date: statistic.date_highest,
+            })
+        })
+        .collect::<Vec<_>>();
+    records.sort_by(|a, b| (&a.station_id, a.year).cmp(&(&b.station_id, b.year)));
+    records
+}
+
+// each reservoir's water year statistics, with capacity filled in when known
+fn water_statistics_of(
+    hash_map: &HashMap<String, ReservoirObservations>,
+) -> HashMap<String, Vec<WaterYearStatistics>> {
+    let capacities = Reservoir::get_reservoir_vector()
+        .into_iter()
+        .map(|reservoir| (reservoir.station_id, reservoir.capacity))
+        .collect::<HashMap<String, i32>>();
+    hash_map
+        .get_water_years_from_reservoir_observations()
+        .into_iter()
+        .map(|(station_id, water_years)| {
+            let capacity = capacities.get(&station_id).copied();
+            let water_statistics = water_years
+                .iter()
+                .map(|water_year| WaterYearStatistics::from(water_year).with_capacity(capacity))
+                .collect::<Vec<WaterYearStatistics>>();
+            (station_id, water_statistics)
+        })
+        .collect::<HashMap<String, Vec<WaterYearStatistics>>>()
+}
+
+// one `station_id,water_year,low,high` row per reservoir water year, which
+// loads straight into a data frame
+pub fn tidy_water_years_csv(
+    water_statistics: &HashMap<String, Vec<WaterYearStatistics>>,
+) -> String {
+    let mut writer = Writer::from_writer(vec![]);
+    writer
+        .write_record(["station_id", "water_year", "low", "high"])
+        .expect("Error: writing record failed");
+    for record in min_max_records(water_statistics) {
+        writer
+            .write_record([
+                record.station_id,
+                record.year.to_string(),
+                record.min_value.to_string(),
+                record.max_value.to_string(),
+            ])
+            .expect("Error: writing record failed");
+    }
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+fn write_water_years(
+    file_path: &Path,
+    hash_map: &HashMap<String, ReservoirObservations>,
+    tidy: bool,
+) {
+    let written = if tidy {
+        let csv_out = tidy_water_years_csv(&water_statistics_of(hash_map));
+        write_atomically(file_path, |fs| fs.write_all(csv_out.as_bytes()))
+    } else {
+        write_atomically(file_path, |fs| {
+            to_writer(fs, hash_map).map_err(io::Error::other)
+        })
+    };
+    if written.is_err() {
+        panic!("writing water years file failed");
+    }
+}
+
+fn write_min_max(
+    file_path: &Path,
+    water_statistics: &HashMap<String, Vec<WaterYearStatistics>>,
+    format: MinMaxFormat,
+) {
+    let written = write_atomically(file_path, |fs| match format {
+        MinMaxFormat::Cbor => to_writer(fs, water_statistics).map_err(io::Error::other),
+        MinMaxFormat::Json => serde_json::to_writer_pretty(fs, &min_max_records(water_statistics))
+            .map_err(io::Error::other),
+    });
+    if written.is_err() {
+        panic!("writing min_max file failed");
+    }
 }
 
 impl TryFrom<Commands> for Peruse {
@@ -36,6 +153,8 @@ impl TryFrom<Commands> for Peruse {
                 min_max_output,
                 start_date,
                 end_date,
+                format,
+                tidy,
             } => Ok(Peruse {
                 summation_output,
                 reservoir_output,
@@ -43,6 +162,8 @@ impl TryFrom<Commands> for Peruse {
                 min_max_output,
                 start_date,
                 end_date,
+                format,
+                tidy,
             }),
             _ => Err(TryFromError::PeruseError),
         }
@@ -88,8 +209,9 @@ impl Run for Peruse {
             Some(file_path) => {
                 info!("running summation now");
                 let csv_out = cdec_data.run_csv_v2();
-                let mut fs = std::fs::File::create(file_path.as_path()).unwrap();
-                if fs.write_all(csv_out.as_bytes()).is_err() {
+                if write_atomically(file_path.as_path(), |fs| fs.write_all(csv_out.as_bytes()))
+                    .is_err()
+                {
                     panic!("writing csv file failed");
                 }
                 info!("summation file path: {:?}", file_path);
@@ -100,8 +222,9 @@ impl Run for Peruse {
             Some(file_path) => {
                 info!("running summation now");
                 let csv_out = cdec_data.run_csv();
-                let mut fs = std::fs::File::create(file_path.as_path()).unwrap();
-                if fs.write_all(csv_out.as_bytes()).is_err() {
+                if write_atomically(file_path.as_path(), |fs| fs.write_all(csv_out.as_bytes()))
+                    .is_err()
+                {
                     panic!("writing csv file failed");
                 }
                 info!("reservoir file path: {:?}", file_path);
@@ -113,7 +236,7 @@ impl Run for Peruse {
             (Some(water_years_file_path), Some(min_max_file_path)) => {
                 info!("calculating water years");
                 let mut observation_ranges = cdec_data.clone();
-                observation_ranges.interpolate_reservoir_observations();
+                observation_ranges.par_interpolate_reservoir_observations();
                 let mut observations = observation_ranges
                     .into_iter()
                     .flat_map(|observation_range| observation_range.observations)
@@ -149,31 +272,16 @@ impl Run for Peruse {
                     hash_map.insert(station_id, reservoir_observations);
                 }
 
-                let water_years_from_observable_ranges =
-                    hash_map.get_water_years_from_reservoir_observations();
-
-                let water_statistics = water_years_from_observable_ranges
-                    .into_iter()
-                    .map(|(station_id, water_years)| {
-                        let water_statistics = water_years
-                            .iter()
-                            .map(|water_year| water_year.into())
-                            .collect::<Vec<WaterYearStatistics>>();
-                        (station_id, water_statistics)
-                    })
-                    .collect::<HashMap<String, Vec<WaterYearStatistics>>>();
+                let water_statistics = water_statistics_of(&hash_map);
 
-                let water_years_fs =
-                    std::fs::File::create(water_years_file_path.as_path()).unwrap();
-                to_writer(water_years_fs, &hash_map).expect("failed to write water years file");
+                write_water_years(water_years_file_path.as_path(), &hash_map, self.tidy);
 
-                let min_max_fs = std::fs::File::create(min_max_file_path.as_path()).unwrap();
-                to_writer(min_max_fs, &water_statistics).expect("failed to write min_max file");
+                write_min_max(min_max_file_path.as_path(), &water_statistics, self.format);
             }
             (Some(water_years_file_path), None) => {
                 info!("calculating water years");
                 let mut observation_ranges = cdec_data.clone();
-                observation_ranges.interpolate_reservoir_observations();
+                observation_ranges.par_interpolate_reservoir_observations();
                 let mut observations = observation_ranges
                     .into_iter()
                     .flat_map(|observation_range| observation_range.observations)
@@ -209,14 +317,12 @@ impl Run for Peruse {
                     hash_map.insert(station_id, reservoir_observations);
                 }
 
-                let water_years_fs =
-                    std::fs::File::create(water_years_file_path.as_path()).unwrap();
-                to_writer(water_years_fs, &hash_map).expect("failed to write water years file");
+                write_water_years(water_years_file_path.as_path(), &hash_map, self.tidy);
             }
             (None, Some(min_max_file_path)) => {
                 info!("calculating water years");
                 let mut observation_ranges = cdec_data.clone();
-                observation_ranges.interpolate_reservoir_observations();
+                observation_ranges.par_interpolate_reservoir_observations();
                 let mut observations = observation_ranges
                     .into_iter()
                     .flat_map(|observation_range| observation_range.observations)
@@ -252,23 +358,148 @@ impl Run for Peruse {
                     hash_map.insert(station_id, reservoir_observations);
                 }
 
-                let water_years_from_observable_ranges =
-                    hash_map.get_water_years_from_reservoir_observations();
+                let water_statistics = water_statistics_of(&hash_map);
 
-                let water_statistics = water_years_from_observable_ranges
-                    .into_iter()
-                    .map(|(station_id, water_years)| {
-                        let water_statistics = water_years
-                            .iter()
-                            .map(|water_year| water_year.into())
-                            .collect::<Vec<WaterYearStatistics>>();
-                        (station_id, water_statistics)
-                    })
-                    .collect::<HashMap<String, Vec<WaterYearStatistics>>>();
-
-                let min_max_fs = std::fs::File::create(min_max_file_path.as_path()).unwrap();
-                to_writer(min_max_fs, &water_statistics).expect("failed to write min_max file");
+                write_min_max(min_max_file_path.as_path(), &water_statistics, self.format);
             }
         };
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::{min_max_records, write_water_years, MinMaxRecord};
+    use cdec::{
+        observation::DataRecording,
+        reservoir_observations::{GetWaterYears, ReservoirObservations},
+        survey::{Survey, Tap},
+        water_year::WaterYearStatistics,
+    };
+    use chrono::NaiveDate;
+    use std::{
+        collections::{BTreeSet, HashMap},
+        fs::{read_to_string, File},
+    };
+
+    fn statistics(year: i32, lowest_value: f64, highest_value: f64) -> WaterYearStatistics {
+        WaterYearStatistics {
+            year,
+            date_lowest: NaiveDate::from_ymd_opt(year + 1, 9, 1).unwrap(),
+            date_highest: NaiveDate::from_ymd_opt(year + 1, 4, 1).unwrap(),
+            highest_value,
+            lowest_value,
+            mean_value: (lowest_value + highest_value) / 2.0,
+            median_value: (lowest_value + highest_value) / 2.0,
+            capacity: None,
+        }
+    }
+
+    #[test]
+    fn test_min_max_records_round_trip_json() {
+        let water_statistics = HashMap::from([
+            (
+                String::from("SHA"),
+                vec![
+                    statistics(2021, 1000.0, 3000.0),
+                    statistics(2020, 1500.0, 4000.0),
+                ],
+            ),
+            (String::from("ORO"), vec![statistics(2021, 800.0, 2500.0)]),
+        ]);
+        let records = min_max_records(&water_statistics);
+        let json = serde_json::to_string(&records).unwrap();
+        let actual: Vec<MinMaxRecord> = serde_json::from_str(&json).unwrap();
+        assert_eq!(actual, records);
+        let keys = actual
+            .iter()
+            .map(|record| (record.station_id.as_str(), record.year))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![("ORO", 2021), ("SHA", 2020), ("SHA", 2021)]);
+        assert_eq!(actual[2].min_value, 1000.0);
+        assert_eq!(
+            actual[2].min_date,
+            NaiveDate::from_ymd_opt(2022, 9, 1).unwrap()
+        );
+        assert_eq!(actual[2].max_value, 3000.0);
+        assert_eq!(
+            actual[2].max_date,
+            NaiveDate::from_ymd_opt(2022, 4, 1).unwrap()
+        );
+    }
+
+    fn daily_surveys(station_id: &str, dates: &[(i32, u32, u32)]) -> ReservoirObservations {
+        let observations = dates
+            .iter()
+            .enumerate()
+            .map(|(i, (year, month, day))| {
+                let date = NaiveDate::from_ymd_opt(*year, *month, *day).unwrap();
+                Survey::Daily(Tap {
+                    station_id: String::from(station_id),
+                    date_observation: date,
+                    date_recording: date,
+                    value: DataRecording::Recording(1000 + i as u32),
+                })
+            })
+            .collect::<Vec<_>>();
+        ReservoirObservations {
+            start_date: observations.first().unwrap().get_tap().date_observation,
+            end_date: observations.last().unwrap().get_tap().date_observation,
+            observations,
+        }
+    }
+
+    #[test]
+    fn test_tidy_water_years_csv_has_a_row_per_water_year() {
+        // SHA spans the 2020 and 2021 water years, ORO only 2021
+        let hash_map = HashMap::from([
+            (
+                String::from("SHA"),
+                daily_surveys(
+                    "SHA",
+                    &[(2020, 11, 1), (2021, 3, 1), (2021, 11, 1), (2022, 3, 1)],
+                ),
+            ),
+            (
+                String::from("ORO"),
+                daily_surveys("ORO", &[(2021, 12, 1), (2022, 4, 1)]),
+            ),
+        ]);
+        let wide_path = std::env::temp_dir().join("cmd-test-water-years-wide.cbor");
+        let tidy_path = std::env::temp_dir().join("cmd-test-water-years-tidy.csv");
+        write_water_years(wide_path.as_path(), &hash_map, false);
+        write_water_years(tidy_path.as_path(), &hash_map, true);
+        // the wide output has a row per reservoir, split into a column per water year
+        let wide: HashMap<String, ReservoirObservations> =
+            serde_cbor::from_reader(File::open(&wide_path).unwrap()).unwrap();
+        let wide_cells = wide
+            .get_water_years_from_reservoir_observations()
+            .into_iter()
+            .flat_map(|(station_id, water_years)| {
+                water_years
+                    .iter()
+                    .map(|water_year| {
+                        (
+                            station_id.clone(),
+                            WaterYearStatistics::from(water_year).year,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<BTreeSet<_>>();
+        assert_eq!(wide.len(), 2);
+        assert_eq!(wide_cells.len(), 3);
+        let tidy = read_to_string(&tidy_path).unwrap();
+        let lines = tidy.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "station_id,water_year,low,high");
+        let tidy_cells = lines[1..]
+            .iter()
+            .map(|line| {
+                let fields = line.split(',').collect::<Vec<_>>();
+                (String::from(fields[0]), fields[1].parse::<i32>().unwrap())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(tidy_cells.len(), wide_cells.len());
+        assert_eq!(tidy_cells.into_iter().collect::<BTreeSet<_>>(), wide_cells);
+        assert_eq!(lines[1], "ORO,2021,1000,1001");
+    }
+}
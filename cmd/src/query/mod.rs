@@ -1,13 +1,17 @@
-use crate::run::get_surveys_of_reservoirs_v2;
-use crate::Commands;
+use crate::run::get_surveys_from_source;
+use crate::{Commands, OutputFormat, Source};
 //use cdec::observable::ObservableRange;
-use cdec::observable::ObservableRangeRunner;
-use cdec::reservoir::{CSV_OBJECT, CSV_OBJECT_NO_POWELL_NO_MEAD};
+use cdec::data_source::CdecSource;
+use cdec::observable::{ObservableRangeRunner, STALE_DATA_DAYS};
+use cdec::reservoir::{Reservoir, CSV_OBJECT, CSV_OBJECT_NO_POWELL_NO_MEAD};
 
-use chrono::{Local, NaiveDate};
-use log::info;
-use std::{io::Write, path::PathBuf};
-use utils::error::date_error;
+use chrono::Local;
+use log::{info, warn};
+use reqwest::Client;
+use std::{collections::HashMap, io::Write, path::PathBuf};
+use utils::dates::parse_date_range;
+use utils::error::date_range_error;
+use utils::files::write_atomically;
 use utils::{error::TryFromError, run::Run};
 
 pub struct Query {
@@ -21,6 +25,20 @@ pub struct Query {
     pub end_date: Option<String>,
     // flag to only include California Reservoirs,
     pub california_only: bool,
+    // output of reservoir locations as GeoJSON
+    pub geojson_output: Option<PathBuf>,
+    // file format of the reservoir output
+    pub format: OutputFormat,
+    // retries after the first attempt of each CDEC request
+    pub max_retries: u32,
+    // number of reservoirs fetched from CDEC at once
+    pub concurrency: usize,
+    // reservoir fetches started each second, 0 is unlimited
+    pub fetches_per_second: u32,
+    // CDEC sensor the surveys are read from, 15 is storage
+    pub sensor_code: u32,
+    // backend the reservoirs and surveys are fetched from
+    pub source: Source,
 }
 
 impl TryFrom<Commands> for Query {
@@ -34,12 +52,26 @@ impl TryFrom<Commands> for Query {
                 start_date,
                 end_date,
                 california_only,
+                geojson_output,
+                format,
+                max_retries,
+                concurrency,
+                fetches_per_second,
+                sensor_code,
+                source,
             } => Ok(Query {
                 summation_output,
                 reservoir_output,
                 start_date,
                 end_date,
                 california_only,
+                geojson_output,
+                format,
+                max_retries,
+                concurrency,
+                fetches_per_second,
+                sensor_code,
+                source,
             }),
             _ => Err(TryFromError::QueryError),
         }
@@ -49,52 +81,79 @@ impl TryFrom<Commands> for Query {
 impl Run for Query {
     async fn run(self) {
         info!("cdec-tk!");
-        let end_date_final = match self.end_date {
-            None => {
-                // Get Today's Date
-                let now = Local::now();
-                now.date_naive()
-            }
-            Some(end_date_string) => {
-                match NaiveDate::parse_from_str(end_date_string.as_str(), "%Y-%m-%d") {
-                    Ok(d) => d,
-                    Err(err) => {
-                        date_error("Start".to_string(), err);
-                        panic!();
-                    }
+        let (start_date_final, end_date_final) =
+            match parse_date_range(self.start_date, self.end_date) {
+                Ok(date_range) => date_range,
+                Err(err) => {
+                    date_range_error(err);
+                    panic!();
                 }
-            }
-        };
+            };
         info!("end date: {:?}", end_date_final);
-        let start_date_final = match self.start_date {
-            None => {
-                //Oldest Reservoir Record is
-                //LGT,Lagunitas,Lagunitas Lake,Lagunitas Creek,341,1925
-                NaiveDate::from_ymd_opt(1924, 12, 30).unwrap()
+        info!("start date: {:?}", start_date_final);
+        let reservoir_list = {
+            if self.california_only {
+                CSV_OBJECT_NO_POWELL_NO_MEAD
+            } else {
+                CSV_OBJECT
             }
-            Some(start_date_string) => {
-                match NaiveDate::parse_from_str(start_date_string.as_str(), "%Y-%m-%d") {
-                    Ok(d) => d,
-                    Err(err) => {
-                        date_error("Start".to_string(), err);
-                        panic!();
-                    }
+        };
+        match self.geojson_output {
+            None => {}
+            Some(file_path) => {
+                let reservoirs = Reservoir::with_station_coordinates(
+                    Reservoir::get_reservoir_vector_v2(reservoir_list),
+                    &Client::new(),
+                    self.max_retries,
+                    self.concurrency,
+                )
+                .await;
+                let missing = reservoirs
+                    .iter()
+                    .filter(|reservoir| reservoir.latitude.is_none())
+                    .count();
+                if missing > 0 {
+                    warn!(
+                        "{} reservoirs have no coordinates and are left out",
+                        missing
+                    );
+                }
+                let geojson_out = Reservoir::to_geojson(&reservoirs);
+                if write_atomically(file_path.as_path(), |fs| {
+                    fs.write_all(geojson_out.as_bytes())
+                })
+                .is_err()
+                {
+                    panic!("writing geojson file failed");
                 }
+                info!("geojson file path: {:?}", file_path);
             }
         };
-        info!("start date: {:?}", start_date_final);
-        let cdec_data = {
-            if self.california_only {
-                get_surveys_of_reservoirs_v2(
+        let cdec_data = match self.source {
+            Source::Cdec => {
+                let source = CdecSource::new(reservoir_list, self.max_retries)
+                    .with_sensor_code(Some(self.sensor_code));
+                get_surveys_from_source(
+                    &source,
                     &start_date_final,
                     &end_date_final,
-                    CSV_OBJECT_NO_POWELL_NO_MEAD,
+                    &HashMap::new(),
+                    self.concurrency,
+                    self.fetches_per_second,
                 )
                 .await
-            } else {
-                get_surveys_of_reservoirs_v2(&start_date_final, &end_date_final, CSV_OBJECT).await
             }
         };
+        info!("data through: {:?}", cdec_data.latest_observation_date());
+        // compared against today, since a past end date would hide stale data
+        if let Some(staleness_days) = cdec_data.staleness_days(Local::now().date_naive()) {
+            if staleness_days > STALE_DATA_DAYS {
+                warn!(
+                    "latest survey is {} days old, CDEC data may be stale",
+                    staleness_days
+                );
+            }
+        }
 
         match self.summation_output {
             None => {}
@@ -102,8 +161,9 @@ impl Run for Query {
                 info!("running summation now");
                 let csv_out = cdec_data.run_csv_v2();
                 info!("attempting to create file: {:?}", file_path);
-                let mut fs = std::fs::File::create(file_path.as_path()).unwrap();
-                if fs.write_all(csv_out.as_bytes()).is_err() {
+                if write_atomically(file_path.as_path(), |fs| fs.write_all(csv_out.as_bytes()))
+                    .is_err()
+                {
                     panic!("writing csv file failed");
                 }
                 info!("summation file path: {:?}", file_path);
@@ -111,16 +171,35 @@ impl Run for Query {
         };
         match self.reservoir_output {
             None => {}
-            Some(file_path) => {
-                info!("running summation now");
-                let csv_out = cdec_data.run_csv();
-                let mut fs = std::fs::File::create(file_path.as_path()).unwrap();
-
-                if fs.write_all(csv_out.as_bytes()).is_err() {
-                    panic!("writing csv file failed");
+            Some(file_path) => match self.format {
+                OutputFormat::Csv => {
+                    info!("running summation now");
+                    let csv_out = cdec_data.run_csv();
+                    if write_atomically(file_path.as_path(), |fs| fs.write_all(csv_out.as_bytes()))
+                        .is_err()
+                    {
+                        panic!("writing csv file failed");
+                    }
+                    info!("reservoir file path: {:?}", file_path);
                 }
-                info!("reservoir file path: {:?}", file_path);
-            }
+                #[cfg(feature = "parquet")]
+                OutputFormat::Parquet => {
+                    let written = write_atomically(file_path.as_path(), |fs| {
+                        crate::columnar::write_observations_parquet(&cdec_data, fs)
+                            .map_err(std::io::Error::other)
+                    });
+                    if written.is_err() {
+                        panic!("writing parquet file failed");
+                    }
+                    info!("reservoir file path: {:?}", file_path);
+                }
+                #[cfg(not(feature = "parquet"))]
+                OutputFormat::Parquet => {
+                    unreachable!(
+                        "--format parquet is rejected at parsing without the `parquet` feature"
+                    );
+                }
+            },
         };
     }
 }
@@ -1,4 +1,5 @@
 use cdec::{
+    data_source::{CdecSource, DataSource},
     observable::{
         CompressedSurveyBuilder, InterpolateObservableRanges, MonthDatum, ObservableRange,
     },
@@ -8,55 +9,140 @@ use cdec::{
 use chrono::NaiveDate;
 use csv::{StringRecord, Writer};
 use easy_cast::Cast;
-use futures::future::join_all;
+use futures::{stream, Future, StreamExt};
 use log::info;
-use reqwest::Client;
 use std::{
     collections::HashSet,
     collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::Duration,
 };
+use tokio::time::{sleep_until, Instant};
 
-pub async fn get_surveys_of_reservoirs(
+// polite default for the number of reservoirs fetched from CDEC at once
+pub const DEFAULT_CONCURRENCY: usize = 8;
+// polite default for the number of reservoir fetches started each second, 0 is unlimited
+pub const DEFAULT_FETCHES_PER_SECOND: u32 = 4;
+
+// spaces out the start of each fetch so CDEC isn't hammered
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn per_second(fetches_per_second: u32) -> Self {
+        let interval = match fetches_per_second {
+            0 => Duration::ZERO,
+            n => Duration::from_secs(1) / n,
+        };
+        RateLimiter {
+            interval,
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    // takes the next free slot and waits for it without blocking other fetches
+    pub async fn until_ready(&self) {
+        if self.interval.is_zero() {
+            return;
+        }
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + self.interval;
+            slot
+        };
+        sleep_until(slot).await;
+    }
+}
+
+// fetches each reservoir with at most `concurrency` requests in flight, starting
+// at most `fetches_per_second` of them each second.
+// results keep the order of the reservoir list whatever order they complete in
+pub async fn fetch_reservoirs_bounded<F, Fut>(
+    reservoirs: Vec<Reservoir>,
+    concurrency: usize,
+    fetches_per_second: u32,
+    fetch: F,
+) -> Vec<ObservableRange>
+where
+    F: Fn(Reservoir) -> Fut,
+    Fut: Future<Output = Option<ObservableRange>>,
+{
+    let rate_limiter = RateLimiter::per_second(fetches_per_second);
+    let rate_limiter = &rate_limiter;
+    let fetch = &fetch;
+    stream::iter(reservoirs.into_iter().map(|reservoir| async move {
+        rate_limiter.until_ready().await;
+        fetch(reservoir).await
+    }))
+    .buffered(concurrency.max(1))
+    .collect::<Vec<_>>()
+    .await
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+}
+
+// fetches every reservoir the source knows about over the date range.
+// reservoirs with a last known date are only fetched if they changed since then
+pub async fn get_surveys_from_source<S: DataSource>(
+    source: &S,
     start_date: &NaiveDate,
     end_date: &NaiveDate,
+    last_known_dates: &HashMap<String, NaiveDate>,
+    concurrency: usize,
+    fetches_per_second: u32,
 ) -> Vec<ObservableRange> {
-    // 1. get observations from date range
-    let reservoirs = Reservoir::get_reservoir_vector();
-    let client = Client::new();
-    let surveys = join_all(reservoirs.into_iter().map(|reservoir| {
-        let client_ref = &client;
-        let start_date_ref = start_date;
-        let end_date_ref = end_date;
-        async move {
-            reservoir
-                .get_surveys_v2(client_ref, start_date_ref, end_date_ref)
+    fetch_reservoirs_bounded(
+        source.fetch_reservoirs(),
+        concurrency,
+        fetches_per_second,
+        |reservoir| async move {
+            let last_known_date = last_known_dates.get(&reservoir.station_id);
+            source
+                .fetch_observations(&reservoir, start_date, end_date, last_known_date)
                 .await
-        }
-    }))
-    .await;
-    surveys.into_iter().flatten().collect::<Vec<_>>()
+        },
+    )
+    .await
+}
+
+pub async fn get_surveys_of_reservoirs(
+    start_date: &NaiveDate,
+    end_date: &NaiveDate,
+) -> Vec<ObservableRange> {
+    let source = CdecSource::default();
+    get_surveys_from_source(
+        &source,
+        start_date,
+        end_date,
+        &HashMap::new(),
+        DEFAULT_CONCURRENCY,
+        DEFAULT_FETCHES_PER_SECOND,
+    )
+    .await
 }
 
 pub async fn get_surveys_of_reservoirs_v2(
     start_date: &NaiveDate,
     end_date: &NaiveDate,
     reservoir_list: &str,
+    max_retries: u32,
+    concurrency: usize,
+    fetches_per_second: u32,
 ) -> Vec<ObservableRange> {
-    // 1. get observations from date range
-    let reservoirs = Reservoir::get_reservoir_vector_v2(reservoir_list);
-    let client = Client::new();
-    let surveys = join_all(reservoirs.into_iter().map(|reservoir| {
-        let client_ref = &client;
-        let start_date_ref = start_date;
-        let end_date_ref = end_date;
-        async move {
-            reservoir
-                .get_surveys_v2(client_ref, start_date_ref, end_date_ref)
-                .await
-        }
-    }))
-    .await;
-    surveys.into_iter().flatten().collect::<Vec<_>>()
+    let source = CdecSource::new(reservoir_list, max_retries);
+    get_surveys_from_source(
+        &source,
+        start_date,
+        end_date,
+        &HashMap::new(),
+        concurrency,
+        fetches_per_second,
+    )
+    .await
 }
 
 pub async fn run_csv_v2(start_date: &NaiveDate, end_date: &NaiveDate) -> String {
@@ -72,7 +158,7 @@ pub async fn run_csv_v2(start_date: &NaiveDate, end_date: &NaiveDate) -> String
     let mut all_reservoir_observations = get_surveys_of_reservoirs(start_date, end_date).await;
     info!("Surveyed Reseroirs: {}", all_reservoir_observations.len());
     info!("Observations Downloaded");
-    all_reservoir_observations.interpolate_reservoir_observations();
+    all_reservoir_observations.par_interpolate_reservoir_observations();
     info!(
         "Interpolated Reseroirs: {}",
         all_reservoir_observations.len()
@@ -165,3 +251,216 @@ pub async fn run_csv(start_date: &NaiveDate, end_date: &NaiveDate) -> String {
     }
     String::from_utf8(writer.into_inner().unwrap()).unwrap()
 }
+
+#[cfg(test)]
+mod test {
+    use super::{fetch_reservoirs_bounded, get_surveys_from_source, RateLimiter};
+    use cdec::{
+        data_source::{CdecSource, DataSource},
+        observable::ObservableRange,
+        observation::DataRecording,
+        reservoir::Reservoir,
+        survey::{Survey, Tap},
+    };
+    use chrono::NaiveDate;
+    use std::{
+        collections::HashMap,
+        io::{Read, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+    use tokio::time::Instant;
+
+    // serves one survey per reservoir without touching the network,
+    // except for reservoirs it has no data for
+    struct MockSource {
+        reservoirs: Vec<Reservoir>,
+        missing_station_id: &'static str,
+    }
+
+    impl DataSource for MockSource {
+        fn fetch_reservoirs(&self) -> Vec<Reservoir> {
+            self.reservoirs.clone()
+        }
+
+        async fn fetch_observations(
+            &self,
+            reservoir: &Reservoir,
+            start_date: &NaiveDate,
+            _end_date: &NaiveDate,
+            _last_known_date: Option<&NaiveDate>,
+        ) -> Option<ObservableRange> {
+            if reservoir.station_id == self.missing_station_id {
+                return None;
+            }
+            let observable_range: ObservableRange = vec![Survey::Daily(Tap {
+                station_id: reservoir.station_id.clone(),
+                date_observation: *start_date,
+                date_recording: *start_date,
+                value: DataRecording::Recording(reservoir.capacity as u32),
+            })]
+            .into();
+            Some(observable_range)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_surveys_from_mock_source() {
+        let reservoir_list = "ID,DAM,LAKE,STREAM,CAPACITY (AF),YEAR FILL
+VIL,Vail,Vail Reservoir,Temecula Creek,51000,
+ORO,Oroville,Lake Oroville,Feather River,3537577,1969
+SHA,Shasta,Lake Shasta,Sacramento River,4552000,1954
+";
+        let source = MockSource {
+            reservoirs: Reservoir::get_reservoir_vector_v2(reservoir_list),
+            missing_station_id: "VIL",
+        };
+        let start_date = NaiveDate::from_ymd_opt(2022, 2, 15).unwrap();
+        let end_date = NaiveDate::from_ymd_opt(2022, 2, 16).unwrap();
+        let actual =
+            get_surveys_from_source(&source, &start_date, &end_date, &HashMap::new(), 2, 0)
+                .await
+                .into_iter()
+                .map(|observable_range| {
+                    let survey = &observable_range.observations[0];
+                    (survey.get_tap().station_id.clone(), survey.get_value())
+                })
+                .collect::<Vec<_>>();
+        assert_eq!(
+            actual,
+            vec![
+                (String::from("ORO"), 3537577.0),
+                (String::from("SHA"), 4552000.0)
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fetch_reservoirs_bounded_keeps_list_order() {
+        let reservoir_list = "ID,DAM,LAKE,STREAM,CAPACITY (AF),YEAR FILL
+VIL,Vail,Vail Reservoir,Temecula Creek,51000,
+ORO,Oroville,Lake Oroville,Feather River,3537577,1969
+SHA,Shasta,Lake Shasta,Sacramento River,4552000,1954
+";
+        let reservoirs = Reservoir::get_reservoir_vector_v2(reservoir_list);
+        let date = NaiveDate::from_ymd_opt(2022, 2, 15).unwrap();
+        // the first reservoir in the list takes the longest so completion order is reversed
+        let actual = fetch_reservoirs_bounded(reservoirs, 3, 0, |reservoir| async move {
+            let delay_millis = match reservoir.station_id.as_str() {
+                "VIL" => 30,
+                "ORO" => 20,
+                _ => 10,
+            };
+            tokio::time::sleep(Duration::from_millis(delay_millis)).await;
+            let observable_range: ObservableRange = vec![Survey::Daily(Tap {
+                station_id: reservoir.station_id,
+                date_observation: date,
+                date_recording: date,
+                value: DataRecording::Recording(1),
+            })]
+            .into();
+            Some(observable_range)
+        })
+        .await
+        .into_iter()
+        .map(|observable_range| {
+            observable_range.observations[0]
+                .get_tap()
+                .station_id
+                .clone()
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(actual, vec!["VIL", "ORO", "SHA"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_spaces_out_fetches() {
+        let reservoir_list = "ID,DAM,LAKE,STREAM,CAPACITY (AF),YEAR FILL
+VIL,Vail,Vail Reservoir,Temecula Creek,51000,
+ORO,Oroville,Lake Oroville,Feather River,3537577,1969
+SHA,Shasta,Lake Shasta,Sacramento River,4552000,1954
+FOL,Folsom,Folsom Lake,American River,977000,1956
+";
+        let reservoirs = Reservoir::get_reservoir_vector_v2(reservoir_list);
+        let started_at = Mutex::new(Vec::new());
+        // enough concurrency to start every fetch at once, so only the limiter spaces them out
+        let actual = fetch_reservoirs_bounded(reservoirs, 4, 20, |_reservoir| async {
+            started_at.lock().unwrap().push(Instant::now());
+            None
+        })
+        .await;
+        assert!(actual.is_empty());
+        let started_at = started_at.into_inner().unwrap();
+        assert_eq!(started_at.len(), 4);
+        for pair in started_at.windows(2) {
+            assert_eq!(pair[1].duration_since(pair[0]), Duration::from_millis(50));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_unlimited() {
+        let rate_limiter = RateLimiter::per_second(0);
+        let started = Instant::now();
+        for _ in 0..100 {
+            rate_limiter.until_ready().await;
+        }
+        assert_eq!(started.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_get_surveys_from_source_skips_not_modified() {
+        // a CSVDataServlet that has nothing new for VIL since its last known date
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let request_lines = Arc::new(Mutex::new(Vec::new()));
+        let server_request_lines = Arc::clone(&request_lines);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buffer = [0u8; 1024];
+                let read = stream.read(&mut buffer).unwrap();
+                let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+                let request_line = request.lines().next().unwrap().to_string();
+                let response = if request_line.contains("Stations=VIL") {
+                    assert!(request.to_lowercase().contains("if-modified-since"));
+                    String::from("HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n")
+                } else {
+                    let body = "STATION_ID,DURATION,SENSOR_NUMBER,SENSOR_TYPE,DATE TIME,OBS DATE,VALUE,DATA_FLAG,UNITS
+ORO,D,15,STORAGE,20220215 0000,20220215 0000,2500000, ,AF
+ORO,D,15,STORAGE,20220216 0000,20220216 0000,2500100, ,AF
+";
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                };
+                server_request_lines.lock().unwrap().push(request_line);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let reservoir_list = "ID,DAM,LAKE,STREAM,CAPACITY (AF),YEAR FILL
+VIL,Vail,Vail Reservoir,Temecula Creek,51000,
+ORO,Oroville,Lake Oroville,Feather River,3537577,1969
+";
+        let source = CdecSource::new(reservoir_list, 0).with_base_url(base_url.as_str());
+        let start_date = NaiveDate::from_ymd_opt(2022, 2, 15).unwrap();
+        let end_date = NaiveDate::from_ymd_opt(2022, 2, 16).unwrap();
+        let last_known_dates = HashMap::from([(String::from("VIL"), start_date)]);
+        let actual =
+            get_surveys_from_source(&source, &start_date, &end_date, &last_known_dates, 1, 0).await;
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].observations[0].get_tap().station_id, "ORO");
+        assert_eq!(actual[0].observations.len(), 2);
+        // VIL is skipped after its dailies come back 304, without asking for monthlies
+        let vail_requests = request_lines
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request_line| request_line.contains("Stations=VIL"))
+            .count();
+        assert_eq!(vail_requests, 1);
+    }
+}
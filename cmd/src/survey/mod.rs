@@ -1,9 +1,26 @@
+use crate::run::{get_surveys_from_source, DEFAULT_CONCURRENCY, DEFAULT_FETCHES_PER_SECOND};
+use cdec::{
+    compression::{
+        append_surveys_to_tar_file, compress_csv_to_tar_file, decompress_stream, CompressionFormat,
+    },
+    data_source::{CdecSource, DataSource},
+    reservoir::{CSV_OBJECT, DEFAULT_MAX_RETRIES},
+    survey::{CompressedStringRecord, Survey as StationSurvey},
+};
 use chrono::{Local, NaiveDate};
-use log::LevelFilter;
-use std::{path::PathBuf, str::FromStr};
-use utils::{error::date_error, run::Run};
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use utils::{error::date_error, files::write_atomically, run::Run};
 
 const DEFAULT_OUTPUT_PATH: &str = "output.tar.xz";
+// name of the csv file inside the archive
+const CSV_FILE_NAME: &str = "output.csv";
 
 pub struct Survey {
     // if there is already existing data to append to
@@ -18,12 +35,69 @@ pub struct Survey {
     pub end_date: Option<String>,
 }
 
+// the date of each station's latest survey in an archive, streamed from the
+// file so only the dates are kept
+fn last_known_dates(archive: File) -> io::Result<HashMap<String, NaiveDate>> {
+    let mut dates: HashMap<String, NaiveDate> = HashMap::new();
+    for record in decompress_stream(archive) {
+        let survey = StationSurvey::from(CompressedStringRecord(record?));
+        let tap = survey.get_tap();
+        dates
+            .entry(tap.station_id.clone())
+            .and_modify(|date| *date = (*date).max(tap.date_observation))
+            .or_insert(tap.date_observation);
+    }
+    Ok(dates)
+}
+
+// fetches the surveys of every station the source knows about and merges them
+// into the existing archive, writing the result to output_path. stations that
+// haven't changed since their latest archived survey are skipped, and without
+// an existing archive a new xz archive is started
+pub async fn append_to_archive<S: DataSource>(
+    source: &S,
+    existing_data_input: &Path,
+    output_path: &Path,
+    start_date: &NaiveDate,
+    end_date: &NaiveDate,
+) -> io::Result<()> {
+    let (existing_archive, last_known_dates) = if existing_data_input.exists() {
+        (
+            std::fs::read(existing_data_input)?,
+            last_known_dates(File::open(existing_data_input)?)?,
+        )
+    } else {
+        warn!(
+            "no existing data at {:?}, starting a new archive",
+            existing_data_input
+        );
+        (
+            compress_csv_to_tar_file(CSV_FILE_NAME, &[], CompressionFormat::Lzma),
+            HashMap::new(),
+        )
+    };
+    info!("{} stations already surveyed", last_known_dates.len());
+    let new_surveys = get_surveys_from_source(
+        source,
+        start_date,
+        end_date,
+        &last_known_dates,
+        DEFAULT_CONCURRENCY,
+        DEFAULT_FETCHES_PER_SECOND,
+    )
+    .await
+    .into_iter()
+    .flat_map(|observable_range| observable_range.observations)
+    .collect::<Vec<_>>();
+    info!("{} surveys fetched", new_surveys.len());
+    let merged_archive = append_surveys_to_tar_file(&existing_archive, CSV_FILE_NAME, new_surveys)?;
+    write_atomically(output_path, |fs| fs.write_all(&merged_archive))
+}
+
 impl Run for Survey {
     async fn run(self) {
-        // log::set_logger(&MY_LOGGER).unwrap();
-        log::set_max_level(LevelFilter::Info);
         // dates
-        let _start_date_final = match self.start_date {
+        let start_date_final = match self.start_date {
             None => {
                 //Oldest Reservoir Record is
                 //LGT,M,1924-12-31,434
@@ -41,7 +115,7 @@ impl Run for Survey {
             }
         };
 
-        let _end_date_final = match self.end_date {
+        let end_date_final = match self.end_date {
             None => {
                 // Get Today's Date
                 let now = Local::now();
@@ -58,7 +132,7 @@ impl Run for Survey {
             }
         };
         // get files
-        let _existing_data_input_path = match self.existing_data_input {
+        let existing_data_input_path = match self.existing_data_input {
             None => {
                 let file_path = PathBuf::from_str(DEFAULT_OUTPUT_PATH);
                 file_path.unwrap()
@@ -72,12 +146,180 @@ impl Run for Survey {
             }
             Some(file_path) => file_path,
         };
-        let _reservoir_output = match self.reservoir_output {
+        let reservoir_output = match self.reservoir_output {
             None => {
                 let file_path = PathBuf::from_str(DEFAULT_OUTPUT_PATH);
                 file_path.unwrap()
             }
             Some(file_path) => file_path,
         };
+        let source = CdecSource::new(CSV_OBJECT, DEFAULT_MAX_RETRIES);
+        if let Err(e) = append_to_archive(
+            &source,
+            existing_data_input_path.as_path(),
+            reservoir_output.as_path(),
+            &start_date_final,
+            &end_date_final,
+        )
+        .await
+        {
+            panic!("appending to {:?} failed: {}", reservoir_output, e);
+        }
+        info!("reservoir file path: {:?}", reservoir_output);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{append_to_archive, CSV_FILE_NAME};
+    use cdec::{
+        compression::{compress_csv_to_tar_file, decompress_stream, CompressionFormat},
+        data_source::DataSource,
+        observable::ObservableRange,
+        observation::DataRecording,
+        reservoir::Reservoir,
+        survey::{CompressedStringRecord, Survey, Tap},
+    };
+    use chrono::NaiveDate;
+    use std::{io::Cursor, sync::Mutex};
+
+    // serves one new survey per reservoir, and records the last known date it was asked with
+    struct MockSource {
+        reservoirs: Vec<Reservoir>,
+        last_known_dates: Mutex<Vec<(String, Option<NaiveDate>)>>,
+    }
+
+    impl DataSource for MockSource {
+        fn fetch_reservoirs(&self) -> Vec<Reservoir> {
+            self.reservoirs.clone()
+        }
+
+        async fn fetch_observations(
+            &self,
+            reservoir: &Reservoir,
+            _start_date: &NaiveDate,
+            end_date: &NaiveDate,
+            last_known_date: Option<&NaiveDate>,
+        ) -> Option<ObservableRange> {
+            self.last_known_dates
+                .lock()
+                .unwrap()
+                .push((reservoir.station_id.clone(), last_known_date.copied()));
+            let observable_range: ObservableRange = vec![Survey::Daily(Tap {
+                station_id: reservoir.station_id.clone(),
+                date_observation: *end_date,
+                date_recording: *end_date,
+                value: DataRecording::Recording(300),
+            })]
+            .into();
+            Some(observable_range)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_append_to_archive_merges_existing_data() {
+        let existing_csv = "SHA,D,20230101,100\nSHA,D,20230102,100\nORO,D,20230101,50\n";
+        let existing_path = std::env::temp_dir().join("cmd-test-survey-existing.tar.zst");
+        let output_path = std::env::temp_dir().join("cmd-test-survey-output.tar.zst");
+        std::fs::write(
+            &existing_path,
+            compress_csv_to_tar_file(
+                CSV_FILE_NAME,
+                existing_csv.as_bytes(),
+                CompressionFormat::Zstd,
+            ),
+        )
+        .unwrap();
+        let reservoir_list = "ID,DAM,LAKE,STREAM,CAPACITY (AF),YEAR FILL
+SHA,Shasta,Lake Shasta,Sacramento River,4552000,1954
+VIL,Vail,Vail Reservoir,Temecula Creek,51000,
+";
+        let source = MockSource {
+            reservoirs: Reservoir::get_reservoir_vector_v2(reservoir_list),
+            last_known_dates: Mutex::new(Vec::new()),
+        };
+        let start_date = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+        let end_date = NaiveDate::from_ymd_opt(2023, 1, 3).unwrap();
+        append_to_archive(
+            &source,
+            existing_path.as_path(),
+            output_path.as_path(),
+            &start_date,
+            &end_date,
+        )
+        .await
+        .unwrap();
+        // SHA was asked only for what changed since its latest archived survey
+        let mut last_known_dates = source.last_known_dates.into_inner().unwrap();
+        last_known_dates.sort();
+        assert_eq!(
+            last_known_dates,
+            vec![
+                (String::from("SHA"), NaiveDate::from_ymd_opt(2023, 1, 2)),
+                (String::from("VIL"), None),
+            ]
+        );
+        let merged_archive = std::fs::read(&output_path).unwrap();
+        assert_eq!(
+            CompressionFormat::detect(&merged_archive),
+            CompressionFormat::Zstd
+        );
+        let actual = decompress_stream(Cursor::new(merged_archive))
+            .map(|record| {
+                let survey = Survey::from(CompressedStringRecord(record.unwrap()));
+                let tap = survey.get_tap();
+                (
+                    tap.station_id.clone(),
+                    tap.date_observation,
+                    survey.get_value(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let date = |day| NaiveDate::from_ymd_opt(2023, 1, day).unwrap();
+        assert_eq!(
+            actual,
+            vec![
+                (String::from("ORO"), date(1), 50.0),
+                (String::from("SHA"), date(1), 100.0),
+                (String::from("SHA"), date(2), 100.0),
+                (String::from("SHA"), date(3), 300.0),
+                (String::from("VIL"), date(3), 300.0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_append_to_archive_starts_a_new_archive() {
+        let existing_path = std::env::temp_dir().join("cmd-test-survey-missing.tar.xz");
+        let output_path = std::env::temp_dir().join("cmd-test-survey-new.tar.xz");
+        let _ = std::fs::remove_file(&existing_path);
+        let source = MockSource {
+            reservoirs: Reservoir::get_reservoir_vector_v2(
+                "ID,DAM,LAKE,STREAM,CAPACITY (AF),YEAR FILL
+VIL,Vail,Vail Reservoir,Temecula Creek,51000,
+",
+            ),
+            last_known_dates: Mutex::new(Vec::new()),
+        };
+        let date = NaiveDate::from_ymd_opt(2023, 1, 3).unwrap();
+        append_to_archive(
+            &source,
+            existing_path.as_path(),
+            output_path.as_path(),
+            &date,
+            &date,
+        )
+        .await
+        .unwrap();
+        let new_archive = std::fs::read(&output_path).unwrap();
+        assert_eq!(
+            CompressionFormat::detect(&new_archive),
+            CompressionFormat::Lzma
+        );
+        let records = decompress_stream(Cursor::new(new_archive))
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(&records[0][0], "VIL");
     }
 }
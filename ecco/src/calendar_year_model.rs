@@ -28,6 +28,12 @@ impl Default for CalendarYearModel {
     }
 }
 
+// stations are spread over this many viridis colors
+const NUMBER_OF_STATION_COLORS: usize = 20;
+// the station colors stop short of viridis' yellow end, which is hard to see
+// on the white canvas the charts are drawn on
+const STATION_COLOR_RAMP_END: f32 = 0.85;
+
 pub fn get_colors(number_of_colors: usize) -> Result<Vec<RGBColor>, String> {
     let vec_of_colors = vec![
         // Oranges - 9
@@ -62,3 +68,142 @@ pub fn get_colors(number_of_colors: usize) -> Result<Vec<RGBColor>, String> {
     }
     Err(String::from("too many colors requested"))
 }
+
+// the same "#rrggbb" color for a station on every chart and every render.
+// FNV-1a is used over std's hasher, which is free to change between releases.
+// there are only NUMBER_OF_STATION_COLORS colors for 200+ stations, so stations
+// are bound to share colors: a color is stable for a station, not unique to it
+pub fn station_color(station_id: &str) -> String {
+    let RGBColor(red, green, blue) = station_rgb_color(station_id);
+    format!("#{red:02x}{green:02x}{blue:02x}")
+}
+
+// station_color as a plotters color for drawing series
+pub fn station_rgb_color(station_id: &str) -> RGBColor {
+    let hash = station_id
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    station_colors()[(hash % NUMBER_OF_STATION_COLORS as u64) as usize]
+}
+
+// NUMBER_OF_STATION_COLORS evenly spaced along viridis up to STATION_COLOR_RAMP_END
+fn station_colors() -> Vec<RGBColor> {
+    (0..NUMBER_OF_STATION_COLORS)
+        .map(|i| {
+            let position = i as f32 / (NUMBER_OF_STATION_COLORS - 1) as f32;
+            ViridisRGB::get_color(position * STATION_COLOR_RAMP_END)
+        })
+        .collect()
+}
+
+// colorblind-safe alternatives to the default water year colors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Palette {
+    #[default]
+    Default,
+    // evenly spaced along viridis, any number of colors
+    Viridis,
+    // the eight Okabe-Ito colors
+    Okabe,
+}
+
+pub fn get_colors_palette(
+    number_of_colors: usize,
+    palette: Palette,
+) -> Result<Vec<RGBColor>, String> {
+    match palette {
+        Palette::Default => get_colors(number_of_colors),
+        Palette::Viridis => Ok((0..number_of_colors)
+            .map(|i| {
+                let position = if number_of_colors > 1 {
+                    i as f32 / (number_of_colors - 1) as f32
+                } else {
+                    0.0
+                };
+                ViridisRGB::get_color(position)
+            })
+            .collect()),
+        Palette::Okabe => {
+            let vec_of_colors = [
+                RGBColor(230, 159, 0),
+                RGBColor(86, 180, 233),
+                RGBColor(0, 158, 115),
+                RGBColor(240, 228, 66),
+                RGBColor(0, 114, 178),
+                RGBColor(213, 94, 0),
+                RGBColor(204, 121, 167),
+                RGBColor(0, 0, 0),
+            ];
+            if number_of_colors <= vec_of_colors.len() {
+                return Ok(vec_of_colors[0..number_of_colors].to_vec());
+            }
+            Err(String::from("too many colors requested"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_colors_palette, station_color, station_colors, station_rgb_color, Palette};
+    use plotters::prelude::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_get_colors_palette_is_distinct() {
+        for (palette, max_colors) in [
+            (Palette::Default, 20),
+            (Palette::Viridis, 20),
+            (Palette::Okabe, 8),
+        ] {
+            for number_of_colors in 1..=max_colors {
+                let colors = get_colors_palette(number_of_colors, palette).unwrap();
+                let distinct = colors
+                    .iter()
+                    .map(|color| (color.0, color.1, color.2))
+                    .collect::<HashSet<_>>();
+                assert_eq!(colors.len(), number_of_colors);
+                assert_eq!(distinct.len(), number_of_colors);
+            }
+        }
+        assert!(get_colors_palette(9, Palette::Okabe).is_err());
+        // the same request always gives the same colors so legends stay stable
+        assert_eq!(
+            get_colors_palette(20, Palette::Viridis),
+            get_colors_palette(20, Palette::Viridis)
+        );
+    }
+
+    #[test]
+    fn test_station_color_is_stable() {
+        let palette = station_colors();
+        for station_id in ["SHA", "ORO", "FOL", "VIL"] {
+            let color = station_color(station_id);
+            assert_eq!(color, station_color(station_id));
+            assert_eq!(color.len(), 7);
+            assert!(color.starts_with('#'));
+            let rgb_color = station_rgb_color(station_id);
+            assert!(palette.contains(&rgb_color));
+            assert_eq!(
+                color,
+                format!("#{:02x}{:02x}{:02x}", rgb_color.0, rgb_color.1, rgb_color.2)
+            );
+        }
+        assert_ne!(station_color("SHA"), station_color("ORO"));
+    }
+
+    #[test]
+    fn test_station_colors_skip_yellow() {
+        // viridis ends at #fde725, which barely shows on white
+        let yellow = ViridisRGB::get_color(1.0);
+        let luminance = |RGBColor(red, green, blue): RGBColor| {
+            0.2126 * red as f64 + 0.7152 * green as f64 + 0.0722 * blue as f64
+        };
+        let colors = station_colors();
+        assert!(!colors.contains(&yellow));
+        for color in colors {
+            assert!(luminance(color) < luminance(yellow) - 20.0);
+        }
+    }
+}
@@ -1,23 +1,107 @@
 use chrono::{DateTime, Utc};
-use log::{Level, Metadata, Record};
-pub struct MyLogger;
-pub static MY_LOGGER: MyLogger = MyLogger;
+use log::{LevelFilter, Metadata, Record};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+// environment variable holding the max level, e.g. CWR_LOG=debug
+pub const LOG_LEVEL_ENV: &str = "CWR_LOG";
+// environment variable holding the log format, e.g. CWR_LOG_FORMAT=json
+pub const LOG_FORMAT_ENV: &str = "CWR_LOG_FORMAT";
+// how each log line is written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    // [ts] LEVEL - msg
+    #[default]
+    Text,
+    // {"ts": ..., "level": ..., "msg": ...} for log tooling
+    Json,
+}
+impl LogFormat {
+    fn from_usize(value: usize) -> Self {
+        match value {
+            1 => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+pub struct MyLogger {
+    max_level: AtomicUsize,
+    format: AtomicUsize,
+}
+pub static MY_LOGGER: MyLogger = MyLogger::new(LevelFilter::Info, LogFormat::Text);
+
+impl MyLogger {
+    pub const fn new(max_level: LevelFilter, format: LogFormat) -> Self {
+        MyLogger {
+            max_level: AtomicUsize::new(max_level as usize),
+            format: AtomicUsize::new(format as usize),
+        }
+    }
+
+    fn format_record(&self, now: &DateTime<Utc>, record: &Record) -> String {
+        match self.format() {
+            LogFormat::Text => format!(
+                "[{}] {} - {}",
+                now.to_rfc3339(),
+                record.level(),
+                record.args()
+            ),
+            LogFormat::Json => json!({
+                "ts": now.to_rfc3339(),
+                "level": record.level().as_str(),
+                "msg": record.args().to_string(),
+            })
+            .to_string(),
+        }
+    }
+
+    pub fn set_max_level(&self, max_level: LevelFilter) {
+        self.max_level.store(max_level as usize, Ordering::Relaxed);
+    }
+
+    pub fn format(&self) -> LogFormat {
+        LogFormat::from_usize(self.format.load(Ordering::Relaxed))
+    }
+
+    pub fn set_format(&self, format: LogFormat) {
+        self.format.store(format as usize, Ordering::Relaxed);
+    }
+}
+
+// the level named by CWR_LOG, or Info when it is unset or not a level.
+// there is no environment in the browser so wasm always gets Info
+pub fn level_from_env() -> LevelFilter {
+    parse_level(std::env::var(LOG_LEVEL_ENV).ok().as_deref())
+}
+
+fn parse_level(value: Option<&str>) -> LevelFilter {
+    value
+        .and_then(|level| level.trim().parse::<LevelFilter>().ok())
+        .unwrap_or(LevelFilter::Info)
+}
+
+// the format named by CWR_LOG_FORMAT, or Text when it is unset or unknown
+pub fn format_from_env() -> LogFormat {
+    parse_format(std::env::var(LOG_FORMAT_ENV).ok().as_deref())
+}
+
+fn parse_format(value: Option<&str>) -> LogFormat {
+    match value.map(|format| format.trim().to_lowercase()).as_deref() {
+        Some("json") => LogFormat::Json,
+        _ => LogFormat::Text,
+    }
+}
 
 impl log::Log for MyLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Info
+        metadata.level() as usize <= self.max_level.load(Ordering::Relaxed)
     }
 
     #[cfg(not(target_family = "wasm"))]
     fn log(&self, record: &Record) {
         let now: DateTime<Utc> = Utc::now();
         if self.enabled(record.metadata()) {
-            println!(
-                "[{}] {} - {}",
-                now.to_rfc3339(),
-                record.level(),
-                record.args()
-            );
+            println!("{}", self.format_record(&now, record));
         }
     }
 
@@ -27,16 +111,87 @@ impl log::Log for MyLogger {
         use js_sys::JsString;
         let now: DateTime<Utc> = Utc::now();
         if self.enabled(record.metadata()) {
-            let str_log: JsString = format!(
-                "[{}] {} - {}",
-                now.to_rfc3339(),
-                record.level(),
-                record.args()
-            )
-            .into();
+            let str_log: JsString = self.format_record(&now, record).into();
             gloo_log!(str_log);
         }
     }
 
     fn flush(&self) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_format, parse_level, LogFormat, MyLogger};
+    use chrono::{DateTime, Utc};
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use serde_json::Value;
+
+    fn is_enabled(logger: &MyLogger, level: Level) -> bool {
+        logger.enabled(&Metadata::builder().level(level).build())
+    }
+
+    #[test]
+    fn test_enabled_respects_max_level() {
+        let logger = MyLogger::new(LevelFilter::Info, LogFormat::Text);
+        assert!(is_enabled(&logger, Level::Info));
+        assert!(!is_enabled(&logger, Level::Debug));
+        logger.set_max_level(LevelFilter::Debug);
+        assert!(is_enabled(&logger, Level::Debug));
+        assert!(!is_enabled(&logger, Level::Trace));
+        logger.set_max_level(LevelFilter::Off);
+        assert!(!is_enabled(&logger, Level::Error));
+    }
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level(Some("debug")), LevelFilter::Debug);
+        assert_eq!(parse_level(Some("TRACE")), LevelFilter::Trace);
+        assert_eq!(parse_level(Some("loud")), LevelFilter::Info);
+        assert_eq!(parse_level(None), LevelFilter::Info);
+    }
+
+    #[test]
+    fn test_json_format_parses_back() {
+        let logger = MyLogger::new(LevelFilter::Info, LogFormat::Json);
+        let now: DateTime<Utc> = Utc::now();
+        let line = logger.format_record(
+            &now,
+            &Record::builder()
+                .level(Level::Warn)
+                .args(format_args!("retrying \"{}\"", "SHA"))
+                .build(),
+        );
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["ts"], now.to_rfc3339());
+        assert_eq!(value["level"], "WARN");
+        assert_eq!(value["msg"], "retrying \"SHA\"");
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(parse_format(Some("json")), LogFormat::Json);
+        assert_eq!(parse_format(Some(" JSON ")), LogFormat::Json);
+        assert_eq!(parse_format(Some("text")), LogFormat::Text);
+        assert_eq!(parse_format(Some("yaml")), LogFormat::Text);
+        assert_eq!(parse_format(None), LogFormat::Text);
+    }
+
+    #[test]
+    fn test_set_format() {
+        // switching after construction, as the cli does with the global logger
+        let logger = MyLogger::new(LevelFilter::Info, LogFormat::Text);
+        logger.set_format(LogFormat::Json);
+        assert_eq!(logger.format(), LogFormat::Json);
+        let line = logger.format_record(
+            &Utc::now(),
+            &Record::builder()
+                .level(Level::Info)
+                .args(format_args!("data through 2024-10-01"))
+                .build(),
+        );
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "INFO");
+        logger.set_format(LogFormat::Text);
+        assert_eq!(logger.format(), LogFormat::Text);
+    }
+}
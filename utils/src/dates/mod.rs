@@ -1 +1,80 @@
+use chrono::{format::ParseError, Local, NaiveDate};
 
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, PartialEq)]
+pub enum DateRangeError {
+    Start(ParseError),
+    End(ParseError),
+    Inverted(NaiveDate, NaiveDate),
+}
+
+pub fn parse_date(date_string: &str) -> Result<NaiveDate, ParseError> {
+    NaiveDate::parse_from_str(date_string, DATE_FORMAT)
+}
+
+// the oldest reservoir record is
+// LGT,Lagunitas,Lagunitas Lake,Lagunitas Creek,341,1925
+pub fn default_start_date() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1924, 12, 30).unwrap()
+}
+
+pub fn parse_date_range(
+    start_date: Option<String>,
+    end_date: Option<String>,
+) -> Result<(NaiveDate, NaiveDate), DateRangeError> {
+    let start_date_final = match start_date {
+        None => default_start_date(),
+        Some(start_date_string) => {
+            parse_date(start_date_string.as_str()).map_err(DateRangeError::Start)?
+        }
+    };
+    let end_date_final = match end_date {
+        None => Local::now().date_naive(),
+        Some(end_date_string) => {
+            parse_date(end_date_string.as_str()).map_err(DateRangeError::End)?
+        }
+    };
+    if end_date_final < start_date_final {
+        return Err(DateRangeError::Inverted(start_date_final, end_date_final));
+    }
+    Ok((start_date_final, end_date_final))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_date_range, DateRangeError};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_parse_date_range() {
+        let actual = parse_date_range(
+            Some(String::from("2020-01-01")),
+            Some(String::from("2021-01-01")),
+        );
+        let expected = (
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2021, 1, 1).unwrap(),
+        );
+        assert_eq!(actual, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_date_range_inverted() {
+        let actual = parse_date_range(
+            Some(String::from("2021-01-01")),
+            Some(String::from("2020-01-01")),
+        );
+        let expected = DateRangeError::Inverted(
+            NaiveDate::from_ymd_opt(2021, 1, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+        );
+        assert_eq!(actual, Err(expected));
+    }
+
+    #[test]
+    fn test_parse_date_range_bad_end_date() {
+        let actual = parse_date_range(None, Some(String::from("01/01/2020")));
+        assert!(matches!(actual, Err(DateRangeError::End(_))));
+    }
+}
@@ -1,7 +1,9 @@
 use std::process;
 
+use crate::dates::DateRangeError;
 use chrono::format::ParseError;
 pub enum TryFromError {
+    FindError,
     PeruseError,
     QueryError,
     SurveyError,
@@ -14,3 +16,14 @@ pub fn date_error(date_type: String, err: ParseError) {
     eprintln!("Date must be of YYYY-MM-DD format");
     process::exit(1);
 }
+
+pub fn date_range_error(err: DateRangeError) {
+    match err {
+        DateRangeError::Start(parse_error) => date_error("Start".to_string(), parse_error),
+        DateRangeError::End(parse_error) => date_error("End".to_string(), parse_error),
+        DateRangeError::Inverted(start_date, end_date) => {
+            eprintln!("Date Range Error: start date {start_date} is after end date {end_date}");
+            process::exit(1);
+        }
+    }
+}
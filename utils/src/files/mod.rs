@@ -1 +1,86 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
 
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+// path of the temporary file written before the rename
+pub fn temp_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".tmp")
+}
+
+// path of the copy of the previous version of the file
+pub fn backup_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".bak")
+}
+
+// writes to `<path>.tmp` and renames it over `path` only when `write`
+// succeeds, so an interrupted or failed write never truncates the target.
+// the previous version of the target, if any, is kept at `<path>.bak`.
+pub fn write_atomically<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let temp_file_path = temp_path(path);
+    let written = File::create(&temp_file_path).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp_file_path);
+        return Err(err);
+    }
+    if path.exists() {
+        fs::copy(path, backup_path(path))?;
+    }
+    fs::rename(&temp_file_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("utils-files-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_atomically_keeps_backup() {
+        let dir = scratch_dir("backup");
+        let path = dir.join("reservoirs.csv");
+        fs::write(&path, "old").unwrap();
+        write_atomically(&path, |file| file.write_all(b"new")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "old");
+        assert!(!temp_path(&path).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomically_error_leaves_original_intact() {
+        let dir = scratch_dir("error");
+        let path = dir.join("reservoirs.csv");
+        fs::write(&path, "original").unwrap();
+        let result = write_atomically(&path, |file| {
+            file.write_all(b"trunc")?;
+            Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        assert!(!temp_path(&path).exists());
+        assert!(!backup_path(&path).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,72 @@
+use crate::units::with_thousands_separators;
+
+const SUFFIXES: [(&str, f64); 3] = [("B", 1e9), ("M", 1e6), ("K", 1e3)];
+
+// 4552000.0 -> "4.55M AF"
+pub fn human_af(value: f64) -> String {
+    human_af_with_precision(value, 2)
+}
+
+// acre-feet scaled to K, M or B with at most `precision` decimals, trailing
+// zeros trimmed. values under a thousand are whole acre-feet
+pub fn human_af_with_precision(value: f64, precision: usize) -> String {
+    let factor = 10f64.powi(precision as i32);
+    // rounding can carry into the next suffix, e.g. 999,999 is 1M not 1000K
+    let rounded = |scale: f64| (value.abs() / scale * factor).round() / factor;
+    for (suffix, scale) in SUFFIXES {
+        let scaled = rounded(scale);
+        if scaled >= 1.0 && value.abs() >= 1e3 {
+            let number = format!("{:.*}", precision, scaled);
+            let number = if number.contains('.') {
+                number.trim_end_matches('0').trim_end_matches('.')
+            } else {
+                number.as_str()
+            };
+            let sign = if value < 0.0 { "-" } else { "" };
+            return format!("{sign}{number}{suffix} AF");
+        }
+    }
+    format!("{} AF", with_thousands_separators(value))
+}
+
+// 4552000.0 -> "4,552,000 AF"
+pub fn comma_af(value: f64) -> String {
+    format!("{} AF", with_thousands_separators(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_human_af() {
+        assert_eq!(human_af(4_552_000.0), "4.55M AF");
+        assert_eq!(human_af(3_537_577.0), "3.54M AF");
+        assert_eq!(human_af(1_000_000.0), "1M AF");
+        assert_eq!(human_af(999_999.0), "1M AF");
+        assert_eq!(human_af(51_000.0), "51K AF");
+        assert_eq!(human_af(1_250.0), "1.25K AF");
+        assert_eq!(human_af(22_000_000_000.0), "22B AF");
+        assert_eq!(human_af(-2_500_000.0), "-2.5M AF");
+    }
+
+    #[test]
+    fn test_human_af_sub_thousand() {
+        assert_eq!(human_af(850.0), "850 AF");
+        assert_eq!(human_af(341.4), "341 AF");
+        assert_eq!(human_af(0.0), "0 AF");
+    }
+
+    #[test]
+    fn test_human_af_precision() {
+        assert_eq!(human_af_with_precision(4_552_000.0, 0), "5M AF");
+        assert_eq!(human_af_with_precision(4_552_000.0, 3), "4.552M AF");
+        assert_eq!(human_af_with_precision(600.0, 0), "600 AF");
+    }
+
+    #[test]
+    fn test_comma_af() {
+        assert_eq!(comma_af(4_552_000.0), "4,552,000 AF");
+        assert_eq!(comma_af(850.0), "850 AF");
+    }
+}
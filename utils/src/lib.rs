@@ -1,6 +1,8 @@
 pub mod dates;
 pub mod error;
 pub mod files;
+pub mod format;
 pub mod run;
+pub mod units;
 // pub use error::{date_error, TryFromError};
 // pub use run::Run;
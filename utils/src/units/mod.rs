@@ -0,0 +1,62 @@
+// 43,560 cubic feet at 7.48052 gallons per cubic foot
+pub const GALLONS_PER_ACRE_FOOT: f64 = 325_851.429;
+pub const CUBIC_METERS_PER_ACRE_FOOT: f64 = 1_233.481_837_547_52;
+const INCHES_PER_FOOT: f64 = 12.0;
+
+pub fn acre_feet_to_gallons(acre_feet: f64) -> f64 {
+    acre_feet * GALLONS_PER_ACRE_FOOT
+}
+
+pub fn acre_feet_to_cubic_meters(acre_feet: f64) -> f64 {
+    acre_feet * CUBIC_METERS_PER_ACRE_FOOT
+}
+
+// the volume of snow water equivalent spread evenly over area_acres
+pub fn inches_swe_to_acre_feet(inches: f64, area_acres: f64) -> f64 {
+    inches / INCHES_PER_FOOT * area_acres
+}
+
+// rounds to a whole number and groups the digits by thousands, e.g. 4,552,000
+pub fn with_thousands_separators(value: f64) -> String {
+    let rounded = value.round();
+    let digits = rounded.abs().to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+    if rounded < 0.0 {
+        grouped.push('-');
+    }
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acre_foot_conversions() {
+        assert_eq!(acre_feet_to_gallons(1.0).round(), 325_851.0);
+        assert_eq!(acre_feet_to_cubic_meters(1.0).round(), 1_233.0);
+        assert_eq!(
+            acre_feet_to_gallons(4_552_000.0).round(),
+            1_483_275_704_808.0
+        );
+        // a foot of water over 640 acres
+        assert_eq!(inches_swe_to_acre_feet(12.0, 640.0), 640.0);
+        assert_eq!(inches_swe_to_acre_feet(3.0, 100.0), 25.0);
+    }
+
+    #[test]
+    fn test_with_thousands_separators() {
+        assert_eq!(with_thousands_separators(0.0), "0");
+        assert_eq!(with_thousands_separators(999.4), "999");
+        assert_eq!(with_thousands_separators(1_000.0), "1,000");
+        assert_eq!(with_thousands_separators(325_851.429), "325,851");
+        assert_eq!(with_thousands_separators(4_552_000.0), "4,552,000");
+        assert_eq!(with_thousands_separators(-12_345.0), "-12,345");
+    }
+}
@@ -4,7 +4,10 @@ use cdec::{
     survey::Survey,
 };
 use chrono::NaiveDate;
-use ecco::reservoir_observations::{ReservoirObservations, ReservoirObservationsLike};
+use ecco::{
+    calendar_year_model::station_rgb_color,
+    reservoir_observations::{ReservoirObservations, ReservoirObservationsLike},
+};
 use log::{info, LevelFilter};
 use my_log::MY_LOGGER;
 use plotters::prelude::*;
@@ -187,12 +190,13 @@ impl<'a> ObservationsModel {
             .unwrap();
         chart.configure_mesh().x_labels(10_usize).draw()?;
 
-        // populate the canvas with the data
+        // populate the canvas with the data, in the station's color on every chart
+        let series_color = station_rgb_color(observation_model.selected_reservoir.as_str());
         chart
-            .draw_series(LineSeries::new(values, RED))
+            .draw_series(LineSeries::new(values, series_color))
             .unwrap()
             .label(observation_model.selected_reservoir.clone())
-            .legend(|(x, y)| Rectangle::new([(x - 15, y + 1), (x, y)], RED));
+            .legend(move |(x, y)| Rectangle::new([(x - 15, y + 1), (x, y)], series_color));
         // .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
 
         chart
@@ -5,7 +5,10 @@ use cdec::{
     water_year::{NormalizeWaterYears, WaterYear},
 };
 use chrono::{Datelike, NaiveDate};
-use ecco::{calendar_year_model::get_colors, reservoir_observations::ReservoirObservations};
+use ecco::{
+    calendar_year_model::{get_colors_palette, Palette},
+    reservoir_observations::ReservoirObservations,
+};
 use log::{info, LevelFilter};
 use my_log::MY_LOGGER;
 use plotters::prelude::*;
@@ -133,7 +136,8 @@ impl<'a> ObservationsModel {
         let y_max = water_years_data
             .get_largest_acrefeet_over_n_years(NUMBER_OF_CHARTS_TO_DISPLAY_DEFAULT)
             .unwrap();
-        let colors_for_water_years = get_colors(NUMBER_OF_CHARTS_TO_DISPLAY_DEFAULT).unwrap();
+        let colors_for_water_years =
+            get_colors_palette(NUMBER_OF_CHARTS_TO_DISPLAY_DEFAULT, Palette::Viridis).unwrap();
         // let plot_and_color = water_years_data.iter().zip(colors_for_water_years.iter());
         // set up svg drawing area
         let size = (850u32, 600u32);